@@ -0,0 +1,523 @@
+use crate::handlers::models::is_known_model;
+use crate::models::error::ApiError;
+use crate::state::AppState;
+use crate::token_pool::{fingerprint, parse_tokens};
+use axum::http::HeaderMap;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+// 单个 API key 的使用策略，未设置的限制表示不限
+#[derive(Debug, Default, Deserialize)]
+pub struct KeyPolicy {
+    pub key: String,
+    // 便于在日志中区分团队
+    #[serde(default)]
+    pub name: Option<String>,
+    // 允许使用的模型，为空表示全部
+    #[serde(default)]
+    pub models: Vec<String>,
+    pub requests_per_day: Option<u32>,
+    pub max_concurrent_streams: Option<u32>,
+    // RFC 3339 时间或 YYYY-MM-DD（当天结束时过期）
+    #[serde(default, deserialize_with = "deserialize_expiry")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn parse_expiry(raw: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(raw) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(raw, "%Y-%m-%d").ok()?;
+    Some(date.succ_opt()?.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+fn deserialize_expiry<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(raw) => parse_expiry(&raw)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("无效的过期时间: {}", raw))),
+        None => Ok(None),
+    }
+}
+
+struct KeyState {
+    policy: KeyPolicy,
+    // 当天日期（UTC）与已用请求数
+    daily: Arc<Mutex<(NaiveDate, u32)>>,
+    streams: Arc<AtomicU32>,
+}
+
+// 流式请求结束（包括客户端断开）时释放并发名额
+pub struct StreamGuard(Arc<AtomicU32>);
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// 已占用的当日请求数，未确认就被丢弃（请求没有到达上游）时退还
+struct DailySlot {
+    daily: Arc<Mutex<(NaiveDate, u32)>>,
+    day: NaiveDate,
+    committed: bool,
+}
+
+impl Drop for DailySlot {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        let mut daily = self.daily.lock().unwrap();
+        if daily.0 == self.day {
+            daily.1 = daily.1.saturating_sub(1);
+        }
+    }
+}
+
+// 通过 API key 策略检查的请求，占用当日请求数和并发流式名额
+#[derive(Default)]
+pub struct Admission {
+    stream_guard: Option<StreamGuard>,
+    daily: Option<DailySlot>,
+}
+
+impl Admission {
+    // 上游已接受请求，计入当日请求数；返回的 guard 需要保持到响应结束
+    pub fn commit(self) -> Option<StreamGuard> {
+        if let Some(mut daily) = self.daily {
+            daily.committed = true;
+        }
+        self.stream_guard
+    }
+}
+
+// 代理自身签发的客户端 API key，与上游 Cursor token 分离
+pub struct ApiKeys {
+    keys: HashMap<String, KeyState>,
+}
+
+impl ApiKeys {
+    pub fn new(policies: impl IntoIterator<Item = KeyPolicy>) -> Self {
+        let today = Utc::now().date_naive();
+        let keys = policies
+            .into_iter()
+            .map(|policy| {
+                for model in &policy.models {
+                    if !is_known_model(model) {
+                        tracing::warn!(
+                            "API key {} 允许的模型 {} 不在模型列表中",
+                            policy.label(),
+                            model
+                        );
+                    }
+                }
+                let state = KeyState {
+                    daily: Arc::new(Mutex::new((today, 0))),
+                    streams: Arc::new(AtomicU32::new(0)),
+                    policy,
+                };
+                (state.policy.key.clone(), state)
+            })
+            .collect();
+        Self { keys }
+    }
+
+    // API_KEYS 中的 key 不做限制；API_KEYS_FILE 为 JSON 数组，每项是一个 KeyPolicy
+    pub fn from_env() -> Self {
+        let mut policies: Vec<KeyPolicy> = std::env::var("API_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(|key| KeyPolicy {
+                key: key.to_string(),
+                ..Default::default()
+            })
+            .collect();
+
+        if let Some(path) = std::env::var("API_KEYS_FILE")
+            .ok()
+            .filter(|path| !path.is_empty())
+        {
+            // 策略文件有误时拒绝启动，避免在没有认证的情况下对外提供服务
+            let content = std::fs::read_to_string(&path)
+                .unwrap_or_else(|err| panic!("读取 API key 文件 {} 失败: {}", path, err));
+            let from_file: Vec<KeyPolicy> = serde_json::from_str(&content)
+                .unwrap_or_else(|err| panic!("解析 API key 文件 {} 失败: {}", path, err));
+            policies.extend(from_file);
+        }
+
+        if !policies.is_empty() {
+            tracing::info!("已启用 API key 认证，共 {} 个 key", policies.len());
+        }
+        Self::new(policies)
+    }
+
+    // 未配置任何 key 时沿用旧行为：客户端直接携带 Cursor token
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.contains_key(key)
+    }
+
+    // key 的名称，用于日志和用量统计
+    pub fn label(&self, key: &str) -> Option<String> {
+        self.keys.get(key).map(|state| state.policy.label())
+    }
+
+    // 在请求上游之前按 key 的策略检查本次请求，通过后先占用当日请求数，
+    // 上游接受请求后调用 Admission::commit 确认，否则退还
+    pub fn admit(&self, key: &str, model: &str, stream: bool) -> Result<Admission, ApiError> {
+        let Some(state) = self.keys.get(key) else {
+            return Err(ApiError::InvalidApiKey);
+        };
+        let policy = &state.policy;
+        let now = Utc::now();
+
+        if policy
+            .expires_at
+            .is_some_and(|expires_at| now >= expires_at)
+        {
+            tracing::warn!("API key {} 已过期", policy.label());
+            return Err(ApiError::KeyExpired);
+        }
+        if !policy.models.is_empty() && !policy.models.iter().any(|m| m == model) {
+            tracing::warn!("API key {} 无权使用模型 {}", policy.label(), model);
+            return Err(ApiError::ModelNotAllowed(model.to_string()));
+        }
+
+        let guard = match policy.max_concurrent_streams {
+            Some(limit) if stream => {
+                let acquired =
+                    state
+                        .streams
+                        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                            (current < limit).then_some(current + 1)
+                        });
+                if acquired.is_err() {
+                    tracing::warn!("API key {} 并发流式请求已达上限", policy.label());
+                    return Err(ApiError::TooManyStreams(limit));
+                }
+                Some(StreamGuard(state.streams.clone()))
+            }
+            _ => None,
+        };
+
+        let mut daily = state.daily.lock().unwrap();
+        let today = now.date_naive();
+        if daily.0 != today {
+            *daily = (today, 0);
+        }
+        if let Some(limit) = policy.requests_per_day {
+            if daily.1 >= limit {
+                tracing::warn!("API key {} 已达到每日请求上限", policy.label());
+                return Err(ApiError::DailyQuotaExceeded(limit));
+            }
+        }
+        daily.1 += 1;
+
+        Ok(Admission {
+            stream_guard: guard,
+            daily: Some(DailySlot {
+                daily: state.daily.clone(),
+                day: today,
+                committed: false,
+            }),
+        })
+    }
+}
+
+impl KeyPolicy {
+    // 日志中不输出完整 key
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("{}...", self.key.chars().take(6).collect::<String>()),
+        }
+    }
+}
+
+// 取出 Authorization: Bearer 后的内容，没有 Authorization 时使用 Anthropic SDK 的 x-api-key
+// 或 Gemini 客户端的 x-goog-api-key
+fn bearer_token(headers: &HeaderMap) -> Result<Option<&str>, ApiError> {
+    let Some(value) = headers.get("authorization") else {
+        return match headers
+            .get("x-api-key")
+            .or_else(|| headers.get("x-goog-api-key"))
+        {
+            Some(value) => value
+                .to_str()
+                .map(|key| Some(key.trim()))
+                .map_err(|_| ApiError::MissingApiKey),
+            None => Ok(None),
+        };
+    };
+    let value = value.to_str().map_err(|_| ApiError::MissingApiKey)?;
+    value
+        .strip_prefix("Bearer ")
+        .map(|token| Some(token.trim()))
+        .ok_or(ApiError::MissingApiKey)
+}
+
+// 比较 SHA-256 摘要而不是原文，比较耗时不随密钥前缀匹配的长度变化
+fn keys_match(key: &str, expected: &str) -> bool {
+    Sha256::digest(key.as_bytes()) == Sha256::digest(expected.as_bytes())
+}
+
+// 校验管理接口的密钥
+pub fn authenticate_admin(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(admin_key) = &state.admin_key else {
+        return Err(ApiError::AdminDisabled);
+    };
+    match bearer_token(headers)? {
+        Some(key) if keys_match(key, admin_key) => Ok(()),
+        Some(_) => {
+            tracing::warn!("管理接口密钥校验失败");
+            Err(ApiError::InvalidAdminKey)
+        }
+        None => Err(ApiError::MissingApiKey),
+    }
+}
+
+// 认证通过的客户端
+pub struct Client {
+    // 启用 API key 认证时为客户端使用的 key
+    pub api_key: Option<String>,
+    // 本次请求可用的上游 token
+    pub candidates: Vec<String>,
+    // 区分客户端的指纹：API key 的指纹，直通模式下为客户端所带 token 的指纹
+    pub owner: String,
+}
+
+impl Client {
+    // 按 API key 策略检查本次请求，未启用 API key 认证时不做限制
+    pub fn admit(
+        &self,
+        state: &AppState,
+        model: &str,
+        stream: bool,
+    ) -> Result<Admission, ApiError> {
+        match &self.api_key {
+            Some(key) => state.api_keys.admit(key, model, stream),
+            None => Ok(Admission::default()),
+        }
+    }
+}
+
+// 校验客户端身份并确定可用的上游 token
+pub fn authenticate(state: &AppState, headers: &HeaderMap) -> Result<Client, ApiError> {
+    let bearer = bearer_token(headers)?;
+
+    let client = if state.api_keys.is_enabled() {
+        // 启用 API key 后只使用服务端持有的 token，客户端不再接触 Cursor 会话
+        let key = bearer.ok_or(ApiError::MissingApiKey)?;
+        if !state.api_keys.contains(key) {
+            tracing::warn!("API key 校验失败");
+            return Err(ApiError::InvalidApiKey);
+        }
+        Client {
+            api_key: Some(key.to_string()),
+            candidates: state.token_pool.server_tokens(),
+            owner: fingerprint(key),
+        }
+    } else {
+        // 未携带 Authorization 时，只有显式允许匿名请求才使用服务端配置的 token
+        match bearer {
+            Some(raw) => Client {
+                api_key: None,
+                candidates: parse_tokens(raw),
+                owner: fingerprint(raw),
+            },
+            None if state.allow_anonymous => Client {
+                api_key: None,
+                candidates: state.token_pool.server_tokens(),
+                owner: "anonymous".to_string(),
+            },
+            None => return Err(ApiError::MissingApiKey),
+        }
+    };
+
+    if client.candidates.is_empty() {
+        return Err(if state.api_keys.is_enabled() {
+            ApiError::NoUpstreamToken
+        } else {
+            ApiError::MissingApiKey
+        });
+    }
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> KeyPolicy {
+        KeyPolicy {
+            key: key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn state(api_keys: &[&str], server_tokens: &str) -> AppState {
+        AppState::for_test(
+            parse_tokens(server_tokens),
+            ApiKeys::new(api_keys.iter().map(|k| key(k))),
+            "http://127.0.0.1",
+        )
+    }
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", authorization.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_client_tokens_without_api_keys() {
+        let mut state = state(&[], "server");
+        let client = authenticate(&state, &headers("Bearer a,b")).unwrap();
+        assert_eq!(client.candidates, vec!["a", "b"]);
+        assert_eq!(client.api_key, None);
+        // 不带认证头的请求默认不能使用服务端 token
+        assert!(matches!(
+            authenticate(&state, &HeaderMap::new()),
+            Err(ApiError::MissingApiKey)
+        ));
+        state.allow_anonymous = true;
+        assert_eq!(
+            authenticate(&state, &HeaderMap::new()).unwrap().candidates,
+            vec!["server"]
+        );
+        assert!(matches!(
+            authenticate(&state, &headers("Basic a")),
+            Err(ApiError::MissingApiKey)
+        ));
+    }
+
+    #[test]
+    fn test_api_keys_use_server_tokens() {
+        let state = state(&["sk-team"], "t1,t2");
+        let client = authenticate(&state, &headers("Bearer sk-team")).unwrap();
+        assert_eq!(client.candidates, vec!["t1", "t2"]);
+        assert_eq!(client.api_key.as_deref(), Some("sk-team"));
+        assert!(matches!(
+            authenticate(&state, &headers("Bearer t1")),
+            Err(ApiError::InvalidApiKey)
+        ));
+        assert!(matches!(
+            authenticate(&state, &HeaderMap::new()),
+            Err(ApiError::MissingApiKey)
+        ));
+        let mut anthropic = HeaderMap::new();
+        anthropic.insert("x-api-key", "sk-team".parse().unwrap());
+        assert!(authenticate(&state, &anthropic).is_ok());
+
+        let state = self::state(&["sk-team"], "");
+        assert!(matches!(
+            authenticate(&state, &headers("Bearer sk-team")),
+            Err(ApiError::NoUpstreamToken)
+        ));
+    }
+
+    #[test]
+    fn test_authenticate_admin() {
+        let mut state = state(&["sk-team"], "");
+        assert!(authenticate_admin(&state, &headers("Bearer admin")).is_ok());
+        assert!(matches!(
+            authenticate_admin(&state, &headers("Bearer sk-team")),
+            Err(ApiError::InvalidAdminKey)
+        ));
+        assert!(matches!(
+            authenticate_admin(&state, &headers("Bearer admi")),
+            Err(ApiError::InvalidAdminKey)
+        ));
+        state.admin_key = None;
+        assert!(matches!(
+            authenticate_admin(&state, &headers("Bearer admin")),
+            Err(ApiError::AdminDisabled)
+        ));
+    }
+
+    #[test]
+    fn test_policy_from_json() {
+        let policies: Vec<KeyPolicy> = serde_json::from_str(
+            r#"[{"key":"sk-a","name":"team-a","models":["gpt-4o"],"requests_per_day":100,"max_concurrent_streams":2,"expires_at":"2030-01-31"},{"key":"sk-b"}]"#,
+        )
+        .unwrap();
+        assert_eq!(policies[0].requests_per_day, Some(100));
+        assert_eq!(
+            policies[0].expires_at,
+            DateTime::parse_from_rfc3339("2030-02-01T00:00:00Z")
+                .ok()
+                .map(|t| t.with_timezone(&Utc))
+        );
+        assert!(policies[1].models.is_empty());
+        assert!(
+            serde_json::from_str::<Vec<KeyPolicy>>(r#"[{"key":"k","expires_at":"soon"}]"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_admit_model_and_expiry() {
+        let keys = ApiKeys::new([
+            KeyPolicy {
+                models: vec!["gpt-4o".to_string()],
+                ..key("sk-a")
+            },
+            KeyPolicy {
+                expires_at: parse_expiry("2020-01-01"),
+                ..key("sk-old")
+            },
+        ]);
+        assert!(keys.admit("sk-a", "gpt-4o", false).is_ok());
+        assert!(matches!(
+            keys.admit("sk-a", "claude-3-opus", false),
+            Err(ApiError::ModelNotAllowed(_))
+        ));
+        assert!(matches!(
+            keys.admit("sk-old", "gpt-4o", false),
+            Err(ApiError::KeyExpired)
+        ));
+    }
+
+    #[test]
+    fn test_admit_limits() {
+        let keys = ApiKeys::new([KeyPolicy {
+            requests_per_day: Some(3),
+            max_concurrent_streams: Some(1),
+            ..key("sk-a")
+        }]);
+        let guard = keys.admit("sk-a", "gpt-4o", true).unwrap().commit();
+        assert!(guard.is_some());
+        assert!(matches!(
+            keys.admit("sk-a", "gpt-4o", true),
+            Err(ApiError::TooManyStreams(1))
+        ));
+        // 非流式请求不占用并发名额
+        assert!(keys
+            .admit("sk-a", "gpt-4o", false)
+            .unwrap()
+            .commit()
+            .is_none());
+        drop(guard);
+        // 未确认的请求（如上游失败）退还当日请求数
+        for _ in 0..5 {
+            drop(keys.admit("sk-a", "gpt-4o", false).unwrap());
+        }
+        keys.admit("sk-a", "gpt-4o", true).unwrap().commit();
+        assert!(matches!(
+            keys.admit("sk-a", "gpt-4o", false),
+            Err(ApiError::DailyQuotaExceeded(3))
+        ));
+    }
+}
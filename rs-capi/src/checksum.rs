@@ -0,0 +1,88 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+// 客户端的机器标识，对应 Cursor 的 machineId 与 macMachineId（均为 64 位十六进制）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineIdentity {
+    pub machine_id: String,
+    pub mac_machine_id: String,
+}
+
+impl MachineIdentity {
+    // 未配置时由 token 派生，同一个 token 在重启后仍使用相同的标识，不同 token 互不相同
+    pub fn for_token(token: &str, machine_id: Option<&str>, mac_machine_id: Option<&str>) -> Self {
+        let derive = |salt: &str| format!("{:x}", Sha256::digest(format!("{}:{}", salt, token)));
+        Self {
+            machine_id: machine_id.map_or_else(|| derive("machineId"), str::to_string),
+            mac_machine_id: mac_machine_id.map_or_else(|| derive("macMachineId"), str::to_string),
+        }
+    }
+
+    // 生成 X-Cursor-Checksum：混淆后的时间戳 + machineId + "/" + macMachineId
+    pub fn checksum(&self, now_millis: i64) -> String {
+        format!(
+            "{}{}/{}",
+            encode_timestamp(now_millis),
+            self.machine_id,
+            self.mac_machine_id
+        )
+    }
+}
+
+// 时间戳以约 16 分钟为单位，取低 6 字节，按客户端的方式逐字节混淆后 base64 编码
+fn encode_timestamp(now_millis: i64) -> String {
+    let timestamp = (now_millis / 1_000_000) as u64;
+    let mut bytes: [u8; 6] = timestamp.to_be_bytes()[2..].try_into().unwrap();
+    let mut prev = 165u8;
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = (*byte ^ prev).wrapping_add(index as u8);
+        prev = *byte;
+    }
+    STANDARD.encode(bytes)
+}
+
+// 配置的标识应为 64 位十六进制，否则上游可能拒绝
+pub fn is_valid_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_timestamp() {
+        assert_eq!(encode_timestamp(1_700_000_000_000), "paaotEjt");
+        // 同一时间段内不变
+        assert_eq!(encode_timestamp(1_700_000_999_999), "paaotEjt");
+        assert_ne!(encode_timestamp(1_700_001_000_000), "paaotEjt");
+    }
+
+    #[test]
+    fn test_identity_per_token() {
+        let identity = MachineIdentity::for_token("tok", None, None);
+        assert_eq!(
+            identity.machine_id,
+            "3fb2225b39fc61150662e4dd1707f06b4364c74c162030eeb3677294cd3907b0"
+        );
+        assert!(is_valid_id(&identity.mac_machine_id));
+        assert_ne!(identity.machine_id, identity.mac_machine_id);
+        assert_eq!(identity, MachineIdentity::for_token("tok", None, None));
+        assert_ne!(identity, MachineIdentity::for_token("other", None, None));
+
+        let configured = MachineIdentity::for_token("tok", Some("a"), None);
+        assert_eq!(configured.machine_id, "a");
+        assert_eq!(configured.mac_machine_id, identity.mac_machine_id);
+    }
+
+    #[test]
+    fn test_checksum_format() {
+        let identity = MachineIdentity::for_token("tok", None, None);
+        let checksum = identity.checksum(1_700_000_000_000);
+        let (machine, mac) = checksum.split_once('/').unwrap();
+        assert_eq!(machine.len(), 8 + 64);
+        assert!(machine.starts_with("paaotEjt"));
+        assert_eq!(mac, identity.mac_machine_id);
+    }
+}
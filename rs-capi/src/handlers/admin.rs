@@ -0,0 +1,277 @@
+use crate::auth::authenticate_admin;
+use crate::handlers::completion::cancelled_streams;
+use crate::models::error::ApiError;
+use crate::quota::refresh_all;
+use crate::state::AppState;
+use crate::token_pool::{fingerprint, parse_tokens, TokenSettings};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct AddTokensRequest {
+    // 一个或多个英文逗号分隔的 token，支持 `userId::token` 格式
+    pub token: String,
+    // 按 token 覆盖的客户端标识和代理
+    #[serde(flatten)]
+    pub settings: TokenSettings,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTokenRequest {
+    pub enabled: bool,
+}
+
+// 列出服务端 token 及其冷却、错误状态
+pub async fn list_tokens(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    Ok(Json(serde_json::json!({
+        "object": "list",
+        "data": state.token_pool.statuses(),
+    }))
+    .into_response())
+}
+
+// 服务运行状态，计数从启动时开始
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    Ok(Json(serde_json::json!({
+        "cancelled_streams": cancelled_streams(),
+    }))
+    .into_response())
+}
+
+// 立即查询所有启用的服务端 token 的账号额度
+pub async fn token_quota(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    Ok(Json(serde_json::json!({
+        "object": "list",
+        "data": refresh_all(&state).await,
+    }))
+    .into_response())
+}
+
+// 添加 token，已存在的 token 会被跳过
+pub async fn add_tokens(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    payload: Result<Json<AddTokensRequest>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    let Json(request) = payload.map_err(|err| ApiError::InvalidJson(err.body_text()))?;
+
+    let tokens = parse_tokens(&request.token);
+    if tokens.is_empty() {
+        return Err(ApiError::InvalidRequest {
+            message: "token 不能为空".to_string(),
+            param: "token".to_string(),
+        });
+    }
+    request
+        .settings
+        .validate()
+        .map_err(|message| ApiError::InvalidRequest {
+            message,
+            param: request
+                .settings
+                .identity
+                .invalid_id_field()
+                .unwrap_or("proxy")
+                .to_string(),
+        })?;
+
+    let mut added = Vec::new();
+    let mut skipped = Vec::new();
+    for token in tokens {
+        let id = fingerprint(&token);
+        if state.token_pool.add(token, request.settings.clone()) {
+            added.push(id);
+        } else {
+            skipped.push(id);
+        }
+    }
+    tracing::info!(added = ?added, skipped = ?skipped, "通过管理接口添加 token");
+
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({ "added": added, "skipped": skipped })),
+    )
+        .into_response())
+}
+
+// 启用或停用 token
+pub async fn update_token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    payload: Result<Json<UpdateTokenRequest>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    let Json(request) = payload.map_err(|err| ApiError::InvalidJson(err.body_text()))?;
+
+    if !state.token_pool.set_enabled(&id, request.enabled) {
+        return Err(ApiError::TokenNotFound(id));
+    }
+    tracing::info!(token = %id, enabled = request.enabled, "通过管理接口修改 token");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+// 删除 token
+pub async fn delete_token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    if !state.token_pool.remove(&id) {
+        return Err(ApiError::TokenNotFound(id));
+    }
+    tracing::info!(token = %id, "通过管理接口删除 token");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upstream::{send, test_state};
+
+    async fn call(
+        state: &Arc<AppState>,
+        method: &str,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = send(state, method, uri, Some("Bearer admin"), body).await;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_admin_auth() {
+        let state = test_state(&[]).await;
+        let response = send(&state, "GET", "/admin/tokens", None, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send(&state, "GET", "/admin/status", Some("Bearer wrong"), None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send(
+            &state,
+            "GET",
+            "/admin/tokens/quota",
+            Some("Bearer token"),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let (status, body) = call(&state, "GET", "/admin/status", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["cancelled_streams"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn test_manage_tokens() {
+        let state = test_state(&[]).await;
+
+        let (status, body) = call(
+            &state,
+            "POST",
+            "/admin/tokens",
+            Some(serde_json::json!({"token": "aaa,bbb"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(
+            body["added"],
+            serde_json::json!([fingerprint("aaa"), fingerprint("bbb")])
+        );
+        let (_, body) = call(
+            &state,
+            "POST",
+            "/admin/tokens",
+            Some(serde_json::json!({"token": "aaa"})),
+        )
+        .await;
+        assert_eq!(body["skipped"], serde_json::json!([fingerprint("aaa")]));
+
+        let id = fingerprint("aaa");
+        let (status, _) = call(
+            &state,
+            "PATCH",
+            &format!("/admin/tokens/{}", id),
+            Some(serde_json::json!({"enabled": false})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = call(&state, "GET", "/admin/tokens", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let tokens = body["data"].as_array().unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0]["id"], id);
+        assert_eq!(tokens[0]["enabled"], false);
+        assert_eq!(tokens[1]["enabled"], true);
+
+        for token in ["aaa", "bbb"] {
+            let uri = format!("/admin/tokens/{}", fingerprint(token));
+            let (status, _) = call(&state, "DELETE", &uri, None).await;
+            assert_eq!(status, StatusCode::NO_CONTENT);
+        }
+        let (status, body) = call(&state, "DELETE", &format!("/admin/tokens/{}", id), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "token_not_found");
+        let (status, _) = call(
+            &state,
+            "PATCH",
+            "/admin/tokens/missing",
+            Some(serde_json::json!({"enabled": true})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // 没有启用的服务端 token 时不查询额度
+        let (status, body) = call(&state, "GET", "/admin/tokens/quota", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_add_tokens_invalid_settings() {
+        let state = test_state(&[]).await;
+        for (settings, param) in [
+            (
+                serde_json::json!({"token": "aaa", "proxy": "http://[bad"}),
+                "proxy",
+            ),
+            (
+                serde_json::json!({"token": "aaa", "machine_id": "xyz"}),
+                "machine_id",
+            ),
+            (
+                serde_json::json!({"token": "aaa", "mac_machine_id": "xyz"}),
+                "mac_machine_id",
+            ),
+            (serde_json::json!({"token": " , "}), "token"),
+        ] {
+            let (status, body) = call(&state, "POST", "/admin/tokens", Some(settings)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["error"]["param"], param);
+        }
+        assert!(state.token_pool.statuses().is_empty());
+    }
+}
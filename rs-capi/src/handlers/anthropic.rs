@@ -0,0 +1,293 @@
+use crate::handlers::completion::{
+    read_json, Completion, FinishReason, OutputLimits, StreamFormat,
+};
+use crate::models::anthropic::{MessagesRequest, MessagesResponse, ResponseBlock, Usage};
+use crate::models::error::ApiError;
+use crate::state::AppState;
+use crate::usage::TokenCounts;
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::convert::Infallible;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// 以 Anthropic 格式输出的错误：{"type": "error", "error": {"type": ..., "message": ...}}
+pub struct AnthropicError(ApiError);
+
+impl From<ApiError> for AnthropicError {
+    fn from(error: ApiError) -> Self {
+        Self(error)
+    }
+}
+
+// 按状态码对应 Anthropic 的错误类型
+fn error_type(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "invalid_request_error",
+        StatusCode::UNAUTHORIZED => "authentication_error",
+        StatusCode::PAYMENT_REQUIRED => "billing_error",
+        StatusCode::FORBIDDEN => "permission_error",
+        StatusCode::NOT_FOUND => "not_found_error",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+        _ => "api_error",
+    }
+}
+
+fn error_body(error: &ApiError) -> (StatusCode, serde_json::Value) {
+    let (status, body) = error.status_and_body();
+    (
+        status,
+        serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": error_type(status),
+                "message": body.error.message,
+            },
+        }),
+    )
+}
+
+impl IntoResponse for AnthropicError {
+    fn into_response(self) -> Response {
+        let (status, body) = error_body(&self.0);
+        (status, Json(body)).into_response()
+    }
+}
+
+fn stop_reason(reason: &FinishReason) -> (&'static str, Option<String>) {
+    match reason {
+        FinishReason::Stop => ("end_turn", None),
+        FinishReason::StopSequence(stop) => ("stop_sequence", Some(stop.clone())),
+        FinishReason::Length => ("max_tokens", None),
+    }
+}
+
+// 处理 Anthropic Messages 请求
+pub async fn messages(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    request: Request<Body>,
+) -> Result<Response, AnthropicError> {
+    let request: MessagesRequest = read_json(request).await?;
+    tracing::info!(
+        model = %request.model,
+        stream = request.stream,
+        "messages_request"
+    );
+
+    // 与 chat/completions 相同的 role:content 格式，system 放在最前
+    let formatted_messages = request
+        .system
+        .iter()
+        .map(|system| format!("system:{}", system))
+        .chain(
+            request
+                .messages
+                .iter()
+                .map(|msg| format!("{}:{}", msg.role, msg.content)),
+        )
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let completion = Completion::start(
+        &state,
+        &headers,
+        &request.model,
+        request.stream,
+        &formatted_messages,
+    )
+    .await?;
+    let limits = OutputLimits {
+        stop: request.stop_sequences,
+        max_tokens: Some(request.max_tokens),
+    };
+    let id = format!("msg_{}", Uuid::new_v4().simple());
+
+    if request.stream {
+        let format = MessageEventFormat {
+            id,
+            model: request.model,
+            input_tokens: completion.tokens().prompt,
+        };
+        let stream = completion.stream(limits, format);
+        return Ok(Sse::new(stream).into_response());
+    }
+
+    let output = completion.collect(limits).await?;
+    let (stop_reason, stop_sequence) = stop_reason(&output.finish_reason);
+    Ok(Json(MessagesResponse {
+        id,
+        object: "message".to_string(),
+        role: "assistant".to_string(),
+        model: request.model,
+        content: vec![ResponseBlock::Text { text: output.text }],
+        stop_reason: stop_reason.to_string(),
+        stop_sequence,
+        usage: Usage {
+            input_tokens: output.tokens.prompt,
+            output_tokens: output.tokens.completion,
+        },
+    })
+    .into_response())
+}
+
+// Anthropic 的流式事件序列：message_start、content_block_start、若干 content_block_delta、
+// content_block_stop、message_delta、message_stop
+struct MessageEventFormat {
+    id: String,
+    model: String,
+    input_tokens: u64,
+}
+
+fn event(name: &str, data: serde_json::Value) -> Result<Event, Infallible> {
+    Ok(Event::default().event(name).data(data.to_string()))
+}
+
+impl StreamFormat for MessageEventFormat {
+    type Item = Result<Event, Infallible>;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn start(&mut self) -> Vec<Self::Item> {
+        vec![
+            event(
+                "message_start",
+                serde_json::json!({
+                    "type": "message_start",
+                    "message": {
+                        "id": self.id,
+                        "type": "message",
+                        "role": "assistant",
+                        "model": self.model,
+                        "content": [],
+                        "stop_reason": null,
+                        "stop_sequence": null,
+                        "usage": {"input_tokens": self.input_tokens, "output_tokens": 0},
+                    },
+                }),
+            ),
+            event(
+                "content_block_start",
+                serde_json::json!({
+                    "type": "content_block_start",
+                    "index": 0,
+                    "content_block": {"type": "text", "text": ""},
+                }),
+            ),
+        ]
+    }
+
+    fn delta(&mut self, text: &str) -> Self::Item {
+        event(
+            "content_block_delta",
+            serde_json::json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": {"type": "text_delta", "text": text},
+            }),
+        )
+    }
+
+    fn finish(&mut self, reason: &FinishReason, tokens: TokenCounts) -> Vec<Self::Item> {
+        let (stop_reason, stop_sequence) = stop_reason(reason);
+        vec![
+            event(
+                "content_block_stop",
+                serde_json::json!({"type": "content_block_stop", "index": 0}),
+            ),
+            event(
+                "message_delta",
+                serde_json::json!({
+                    "type": "message_delta",
+                    "delta": {"stop_reason": stop_reason, "stop_sequence": stop_sequence},
+                    "usage": {"output_tokens": tokens.completion},
+                }),
+            ),
+            event("message_stop", serde_json::json!({"type": "message_stop"})),
+        ]
+    }
+
+    fn error(&mut self, error: &ApiError) -> Vec<Self::Item> {
+        vec![event("error", error_body(error).1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upstream::{post_json, test_state};
+
+    #[tokio::test]
+    async fn test_messages() {
+        let state = test_state(&["Hello", " world", "\n\nHuman: hi"]).await;
+
+        let response = post_json(
+            &state,
+            "/v1/messages",
+            serde_json::json!({
+                "model": "claude-3.5-sonnet",
+                "max_tokens": 100,
+                "system": "Be brief.",
+                "stop_sequences": ["\n\nHuman:"],
+                "messages": [{"role": "user", "content": "Hi"}],
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(body["type"], "message");
+        assert_eq!(body["content"][0]["text"], "Hello world");
+        assert_eq!(body["stop_reason"], "stop_sequence");
+        assert_eq!(body["stop_sequence"], "\n\nHuman:");
+
+        let response = post_json(
+            &state,
+            "/v1/messages",
+            serde_json::json!({
+                "model": "claude-3.5-sonnet",
+                "max_tokens": 2,
+                "stream": true,
+                "messages": [{"role": "user", "content": "Hi"}],
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.body();
+        let events: Vec<&str> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("event: "))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop"
+            ]
+        );
+        assert!(body.contains(r#""text":" wo""#));
+        assert!(body.contains(r#""stop_reason":"max_tokens""#));
+
+        // 错误使用 Anthropic 格式
+        let response = post_json(
+            &state,
+            "/v1/messages",
+            serde_json::json!({"model": "claude-3.5-sonnet"}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+    }
+}
@@ -1,87 +1,53 @@
 use axum::body::Body;
-use axum::extract::Request;
+use axum::extract::{Request, State};
 use axum::response::sse::Event;
 use axum::Json;
 use axum::{
-    http::{HeaderMap, StatusCode},
+    http::HeaderMap,
     response::{sse::Sse, IntoResponse, Response},
 };
-use bytes::Bytes;
 
-use futures::channel::mpsc;
-use futures::stream::StreamExt;
-use futures::{SinkExt, Stream};
 use std::convert::Infallible;
-use std::error::Error;
 // use http::HeaderName as HttpHeaderName;
-use crate::hex_utils::{chunk_to_utf8_string, string_to_hex};
+use crate::handlers::completion::{
+    read_json, Completion, FinishReason, OutputLimits, StreamFormat,
+};
 use crate::models;
-use regex::Regex;
-use std::str::FromStr;
-use std::time::Duration;
+use crate::models::error::ApiError;
+use crate::state::AppState;
+use crate::usage::TokenCounts;
+use std::sync::Arc;
 use uuid::Uuid;
 
+fn finish_reason(reason: &FinishReason) -> &'static str {
+    match reason {
+        FinishReason::Stop | FinishReason::StopSequence(_) => "stop",
+        FinishReason::Length => "length",
+    }
+}
+
 // 处理聊天完成请求
 pub async fn chat_completions(
+    State(state): State<Arc<AppState>>,
     headers: HeaderMap,
     request: Request<Body>,
     // Json(chat_request): Json<ChatRequest>,
-) -> Result<Response, StatusCode> {
-    // 提取并打印原始请求体
-    const MAX_BODY_SIZE: usize = 20 * 1024 * 1024;
-
-    let bytes = match axum::body::to_bytes(request.into_body(), MAX_BODY_SIZE).await {
-        Ok(bytes) => bytes,
-        Err(err) => {
-            tracing::error!("读取请求体失败: {}", err);
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    };
-
-    // 打印原始请求体
-    if let Ok(body_str) = String::from_utf8(bytes.to_vec()) {
-        tracing::info!("原始请求体: {}", body_str);
-    }
-
-    // 尝试解析 JSON
-    let chat_request: models::chat::ChatRequest = match serde_json::from_slice(&bytes) {
-        Ok(req) => req,
-        Err(err) => {
-            tracing::error!("JSON解析失败: {}", err);
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    };
-
-    // 验证认证
-    let auth_header = headers
-        .get("authorization")
-        .and_then(|h| h.to_str().ok())
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-
-    if !auth_header.starts_with("Bearer ") {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-
-    let mut auth_token = auth_header.replace("Bearer ", "");
+) -> Result<Response, ApiError> {
+    let chat_request: models::chat::ChatRequest = read_json(request).await?;
 
     // 验证o1模型不支持流式输出
     if chat_request.model.starts_with("o1-") && chat_request.stream {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-    tracing::info!("chat_request: {:?}", chat_request);
-
-    // 处理多个密钥
-    if auth_token.contains(',') {
-        auth_token = auth_token.split(',').next().unwrap().trim().to_string();
-    }
-
-    if auth_token.contains("%3A%3A") {
-        auth_token = auth_token
-            .split("%3A%3A")
-            .nth(1)
-            .unwrap_or(&auth_token)
-            .to_string();
+        return Err(ApiError::InvalidRequest {
+            message: format!("模型 {} 不支持流式输出", chat_request.model),
+            param: "stream".to_string(),
+        });
     }
+    tracing::info!(
+        model = %chat_request.model,
+        stream = chat_request.stream,
+        messages = chat_request.messages.len(),
+        "chat_request"
+    );
 
     // 格式化消息
     // let formatted_messages = chat_request
@@ -106,116 +72,26 @@ pub async fn chat_completions(
         .collect::<Vec<_>>()
         .join("\n");
 
-    // 生成请求数据
-    let hex_data = string_to_hex(&formatted_messages, &chat_request.model);
-    // 准备请求头
-    let request_id = Uuid::new_v4();
-    let headers = reqwest::header::HeaderMap::from_iter([
-        (reqwest::header::CONTENT_TYPE, "application/connect+proto"),
-        (reqwest::header::AUTHORIZATION, &format!("Bearer {}", auth_token)),
-        // 对于标准 HTTP 头部，使用预定义的常量
-        (reqwest::header::HeaderName::from_str("Connect-Accept-Encoding").unwrap(), "gzip,br"),
-        (reqwest::header::HeaderName::from_str("Connect-Protocol-Version").unwrap(), "1"),
-        (reqwest::header::HeaderName::from_str("User-Agent").unwrap(), "connect-es/1.4.0"),
-        (reqwest::header::HeaderName::from_str("X-Amzn-Trace-Id").unwrap(), &format!("Root={}", Uuid::new_v4())),
-        (reqwest::header::HeaderName::from_str("X-Cursor-Checksum").unwrap(), "zo6Qjequ9b9734d1f13c3438ba25ea31ac93d9287248b9d30434934e9fcbfa6b3b22029e/7e4af391f67188693b722eff0090e8e6608bca8fa320ef20a0ccb5d7d62dfdef"),
-        (reqwest::header::HeaderName::from_str("X-Cursor-Client-Version").unwrap(), "0.42.3"),
-        (reqwest::header::HeaderName::from_str("X-Cursor-Timezone").unwrap(), "Asia/Shanghai"),
-        (reqwest::header::HeaderName::from_str("X-Ghost-Mode").unwrap(), "false"),
-        (reqwest::header::HeaderName::from_str("X-Request-Id").unwrap(), &request_id.to_string()),
-        (reqwest::header::HeaderName::from_str("Host").unwrap(), "api2.cursor.sh"),
-    ].iter().map(|(k, v)| (
-        k.clone(),
-        reqwest::header::HeaderValue::from_str(v).unwrap()
-    )));
-
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-        .map_err(|e| {
-            tracing::error!("创建HTTP客户端失败: {:?}", e);
-            tracing::error!(error = %e, "错误详情");
-
-            if let Some(source) = e.source() {
-                tracing::error!(source = %source, "错误源");
-            }
-
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-    let response = client
-        .post("https://api2.cursor.sh/aiserver.v1.AiService/StreamChat")
-        .headers(headers)
-        .body(hex_data)
-        .send()
-        .await
-        .map_err(|e| {
-            tracing::error!("请求失败: {:?}", e);
-            tracing::error!(error = %e, "错误详情");
-
-            // 如果是超时错误
-            if e.is_timeout() {
-                tracing::error!("请求超时");
-            }
-
-            // 如果是连接错误
-            if e.is_connect() {
-                tracing::error!("连接失败");
-            }
-
-            // 如果有请求信息
-            if let Some(url) = e.url() {
-                tracing::error!(url = %url, "请求URL");
-            }
-
-            // 如果有状态码
-            if let Some(status) = e.status() {
-                tracing::error!(status = %status, "HTTP状态码");
-            }
-
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    // 认证、检查 API key 策略并请求上游，遇到认证或额度错误时自动换下一个 token
+    let completion = Completion::start(
+        &state,
+        &headers,
+        &chat_request.model,
+        chat_request.stream,
+        &formatted_messages,
+    )
+    .await?;
 
     if chat_request.stream {
-        let mut chunks = Vec::new();
-        let mut stream = response.bytes_stream();
-
-        while let Some(chunk) = stream.next().await {
-            match chunk {
-                Ok(chunk) => chunks.push(chunk),
-                Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-            }
-        }
-
-        let stream = process_stream(chunks).await;
+        let format = ChatChunkFormat {
+            id: format!("chatcmpl-{}", Uuid::new_v4()),
+        };
+        let stream = completion.stream(OutputLimits::default(), format);
         return Ok(Sse::new(stream).into_response());
     }
 
     // 非流式响应
-    let mut text = String::new();
-    let mut stream = response.bytes_stream();
-
-    while let Some(chunk) = stream.next().await {
-        match chunk {
-            Ok(chunk) => {
-                let res = chunk_to_utf8_string(&chunk);
-                // if !res.is_empty() {
-                text.push_str(&res);
-                // }
-            }
-            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-        }
-    }
-
-    // 清理响应文本
-    let re = Regex::new(r"^.*<\|END_USER\|>").unwrap();
-    text = re.replace(&text, "").to_string();
-
-    let re = Regex::new(r"^\n[a-zA-Z]?").unwrap();
-    text = re.replace(&text, "").trim().to_string();
-
-    let re = Regex::new(r"[\x00-\x1F\x7F]").unwrap();
-    text = re.replace_all(&text, "").to_string();
+    let output = completion.collect(OutputLimits::default()).await?;
 
     let response = models::chat::ChatResponse {
         id: format!("chatcmpl-{}", Uuid::new_v4()),
@@ -226,50 +102,129 @@ pub async fn chat_completions(
             index: 0,
             message: models::chat::ResponseMessage {
                 role: "assistant".to_string(),
-                content: text,
+                content: output.text,
             },
-            finish_reason: "stop".to_string(),
+            finish_reason: finish_reason(&output.finish_reason).to_string(),
         }],
         usage: models::chat::Usage {
-            prompt_tokens: 0,
-            completion_tokens: 0,
-            total_tokens: 0,
+            prompt_tokens: output.tokens.prompt,
+            completion_tokens: output.tokens.completion,
+            total_tokens: output.tokens.prompt + output.tokens.completion,
         },
     };
 
     Ok(Json(response).into_response())
 }
 
-async fn process_stream(
-    chunks: Vec<Bytes>,
-) -> impl Stream<Item = Result<Event, Infallible>> + Send {
-    let (mut tx, rx) = mpsc::channel(100);
-    let response_id = format!("chatcmpl-{}", Uuid::new_v4());
+// OpenAI chat.completion.chunk 格式的流式输出
+struct ChatChunkFormat {
+    id: String,
+}
 
-    tokio::spawn(async move {
-        for chunk in chunks {
-            let text = chunk_to_utf8_string(&chunk);
+impl StreamFormat for ChatChunkFormat {
+    type Item = Result<Event, Infallible>;
 
-            // 只在文本非空时处理和发送
-            if !text.is_empty() {
-                let response = models::chat::StreamResponse {
-                    id: response_id.clone(),
-                    object: "chat.completion.chunk".to_string(),
-                    created: chrono::Utc::now().timestamp(),
-                    choices: vec![models::chat::StreamChoice {
-                        index: 0,
-                        delta: models::chat::Delta { content: text },
-                    }],
-                };
+    fn id(&self) -> &str {
+        &self.id
+    }
 
-                let json_data = serde_json::to_string(&response).unwrap();
-                let _ = tx.send(Ok(Event::default().data(json_data))).await;
-            }
-        }
+    fn delta(&mut self, text: &str) -> Self::Item {
+        let response = models::chat::StreamResponse {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created: chrono::Utc::now().timestamp(),
+            choices: vec![models::chat::StreamChoice {
+                index: 0,
+                delta: models::chat::Delta {
+                    content: text.to_string(),
+                },
+            }],
+        };
+        let json_data = serde_json::to_string(&response).unwrap();
+        Ok(Event::default().data(json_data))
+    }
 
-        // 发送完成标记
-        let _ = tx.send(Ok(Event::default().data("[DONE]"))).await;
-    });
+    // 发送完成标记
+    fn finish(&mut self, _reason: &FinishReason, _tokens: TokenCounts) -> Vec<Self::Item> {
+        vec![Ok(Event::default().data("[DONE]"))]
+    }
 
-    rx
+    // 在完成标记之前发送错误事件
+    fn error(&mut self, error: &ApiError) -> Vec<Self::Item> {
+        let (_, body) = error.status_and_body();
+        let json_data = serde_json::to_string(&body).unwrap();
+        vec![
+            Ok(Event::default().data(json_data)),
+            Ok(Event::default().data("[DONE]")),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::ApiKeys;
+    use crate::state::AppState;
+    use crate::upstream::{post_json, send, spawn_stalled_upstream, test_state};
+    use axum::http::StatusCode;
+    use futures::StreamExt;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_chat_completions() {
+        let state = test_state(&["Hello", " world"]).await;
+
+        let response = post_json(
+            &state,
+            "/v1/chat/completions",
+            serde_json::json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "Hi"}],
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(body["choices"][0]["message"]["content"], "Hello world");
+        assert_eq!(body["choices"][0]["finish_reason"], "stop");
+        assert!(body["usage"]["prompt_tokens"].as_u64().unwrap() > 0);
+        assert_eq!(body["usage"]["completion_tokens"], 3);
+        assert_eq!(
+            body["usage"]["total_tokens"],
+            body["usage"]["prompt_tokens"].as_u64().unwrap() + 3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_forwards_before_upstream_ends() {
+        let (base_url, mut upstream_closed) = spawn_stalled_upstream("Hello").await;
+        let state = Arc::new(AppState::for_test(Vec::new(), ApiKeys::new([]), &base_url));
+
+        let response = send(
+            &state,
+            "POST",
+            "/v1/chat/completions",
+            Some("Bearer token"),
+            Some(serde_json::json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "stream": true,
+            })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().into_data_stream();
+        let first = tokio::time::timeout(Duration::from_secs(5), body.next())
+            .await
+            .expect("上游结束前应收到第一段文本")
+            .unwrap()
+            .unwrap();
+        let first = String::from_utf8(first.to_vec()).unwrap();
+        let chunk: serde_json::Value =
+            serde_json::from_str(first.trim().strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(chunk["object"], "chat.completion.chunk");
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "Hello");
+        // 此时上游仍未结束
+        assert!(upstream_closed.try_recv().is_err());
+    }
 }
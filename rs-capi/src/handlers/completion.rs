@@ -0,0 +1,498 @@
+use crate::auth::{authenticate, Client, StreamGuard};
+use crate::models::error::ApiError;
+use crate::proto::chat::StreamMessage;
+use crate::state::AppState;
+use crate::token_pool::fingerprint;
+use crate::upstream::{send_with_failover, UpstreamChat};
+use crate::usage::{PendingUsage, TokenCounts, CLIENT_CLOSED_REQUEST};
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderMap, StatusCode};
+use futures::Stream;
+use serde::de::DeserializeOwned;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+
+// 请求体大小上限，图片以 base64 内联时请求体可能较大
+const MAX_BODY_SIZE: usize = 20 * 1024 * 1024;
+
+// 客户端提前断开而取消的流式请求数
+static CANCELLED_STREAMS: AtomicU64 = AtomicU64::new(0);
+
+fn record_cancellation(response_id: &str) {
+    let total = CANCELLED_STREAMS.fetch_add(1, Ordering::Relaxed) + 1;
+    tracing::info!(
+        response_id = %response_id,
+        cancelled_total = total,
+        "客户端已断开，取消上游请求"
+    );
+}
+
+// 启动以来因客户端断开而取消的流式请求数
+pub fn cancelled_streams() -> u64 {
+    CANCELLED_STREAMS.load(Ordering::Relaxed)
+}
+
+// 读取并解析 JSON 请求体
+pub async fn read_json<T: DeserializeOwned>(request: Request<Body>) -> Result<T, ApiError> {
+    let bytes = axum::body::to_bytes(request.into_body(), MAX_BODY_SIZE)
+        .await
+        .map_err(|err| {
+            tracing::error!("读取请求体失败: {}", err);
+            ApiError::BodyRead(err.to_string())
+        })?;
+    serde_json::from_slice(&bytes).map_err(|err| {
+        tracing::error!("JSON解析失败: {}", err);
+        ApiError::InvalidJson(err.to_string())
+    })
+}
+
+// 生成结束的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinishReason {
+    // 上游正常结束
+    Stop,
+    // 遇到客户端指定的停止序列
+    StopSequence(String),
+    // 达到客户端指定的最大 token 数
+    Length,
+}
+
+// 客户端指定的输出限制；上游不支持停止序列和最大 token 数，由代理在本地截断
+#[derive(Debug, Clone, Default)]
+pub struct OutputLimits {
+    pub stop: Vec<String>,
+    // 按 estimate_tokens 的规则估算
+    pub max_tokens: Option<u64>,
+}
+
+// 按输出限制过滤上游文本
+struct LimitedOutput {
+    limits: OutputLimits,
+    // 可能是停止序列开头、暂不发送的文本
+    pending: String,
+    ascii_chars: u64,
+    other_chars: u64,
+    finished: Option<FinishReason>,
+}
+
+impl LimitedOutput {
+    fn new(mut limits: OutputLimits) -> Self {
+        limits.stop.retain(|stop| !stop.is_empty());
+        Self {
+            limits,
+            pending: String::new(),
+            ascii_chars: 0,
+            other_chars: 0,
+            finished: None,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished.is_some()
+    }
+
+    fn finish_reason(&self) -> FinishReason {
+        self.finished.clone().unwrap_or(FinishReason::Stop)
+    }
+
+    // 输入一段上游文本，返回可以发送给客户端的部分
+    fn push(&mut self, text: &str) -> String {
+        if self.is_finished() {
+            return String::new();
+        }
+        self.pending.push_str(text);
+        let stop_at = self
+            .limits
+            .stop
+            .iter()
+            .filter_map(|stop| self.pending.find(stop.as_str()).map(|pos| (pos, stop)))
+            .min_by_key(|(pos, _)| *pos)
+            .map(|(pos, stop)| (pos, stop.clone()));
+        let ready = match stop_at {
+            Some((pos, stop)) => {
+                self.finished = Some(FinishReason::StopSequence(stop));
+                self.pending.truncate(pos);
+                std::mem::take(&mut self.pending)
+            }
+            None => {
+                let ready = self.pending.len() - self.held_back();
+                self.pending.drain(..ready).collect()
+            }
+        };
+        self.within_max_tokens(ready)
+    }
+
+    // 上游结束时取出暂存的文本
+    fn flush(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        self.within_max_tokens(rest)
+    }
+
+    // pending 末尾可能是某个停止序列开头的最长部分（字节数）
+    fn held_back(&self) -> usize {
+        self.limits
+            .stop
+            .iter()
+            .filter_map(|stop| {
+                (1..stop.len())
+                    .rev()
+                    .find(|&len| stop.is_char_boundary(len) && self.pending.ends_with(&stop[..len]))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    // 截掉超出最大 token 数的部分
+    fn within_max_tokens(&mut self, mut text: String) -> String {
+        let Some(max_tokens) = self.limits.max_tokens else {
+            return text;
+        };
+        let cut = text.char_indices().find_map(|(index, c)| {
+            if c.is_ascii() {
+                self.ascii_chars += 1;
+            } else {
+                self.other_chars += 1;
+            }
+            (self.ascii_chars.div_ceil(4) + self.other_chars > max_tokens).then_some(index)
+        });
+        if let Some(index) = cut {
+            text.truncate(index);
+            self.pending.clear();
+            self.finished = Some(FinishReason::Length);
+        }
+        text
+    }
+}
+
+// 非流式请求的完整回复
+#[derive(Debug)]
+pub struct CompletionOutput {
+    pub text: String,
+    pub finish_reason: FinishReason,
+    pub tokens: TokenCounts,
+}
+
+// 流式响应的输出格式，由各个接口实现
+pub trait StreamFormat: Send + 'static {
+    type Item: Send + 'static;
+
+    // 响应 id，用于日志
+    fn id(&self) -> &str;
+
+    // 第一段文本之前发送的内容
+    fn start(&mut self) -> Vec<Self::Item> {
+        Vec::new()
+    }
+
+    fn delta(&mut self, text: &str) -> Self::Item;
+
+    // 正常结束时发送的内容
+    fn finish(&mut self, reason: &FinishReason, tokens: TokenCounts) -> Vec<Self::Item>;
+
+    // 中途出错时发送的内容
+    fn error(&mut self, error: &ApiError) -> Vec<Self::Item>;
+}
+
+async fn send_all<T>(tx: &mpsc::Sender<T>, items: Vec<T>) -> bool {
+    for item in items {
+        if tx.send(item).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+// 已通过认证和策略检查、并已连接上游的补全请求，各个接口格式共用
+pub struct Completion {
+    upstream: UpstreamChat,
+    // 请求结束时释放并发名额
+    stream_guard: Option<StreamGuard>,
+    usage: PendingUsage,
+}
+
+impl Completion {
+    // 认证客户端，按 API key 策略检查本次请求，然后按最久未使用的顺序选择 token 请求上游
+    pub async fn start(
+        state: &AppState,
+        headers: &HeaderMap,
+        model: &str,
+        stream: bool,
+        prompt: &str,
+    ) -> Result<Self, ApiError> {
+        let client = authenticate(state, headers)?;
+        Self::start_with(state, client, model, stream, prompt).await
+    }
+
+    // 供需要在请求上游之前使用客户端身份的处理器调用
+    pub async fn start_with(
+        state: &AppState,
+        client: Client,
+        model: &str,
+        stream: bool,
+        prompt: &str,
+    ) -> Result<Self, ApiError> {
+        let admission = client.admit(state, model, stream)?;
+
+        // 记录本次请求的用量
+        let api_key = client.api_key.as_deref().and_then(|key| {
+            let name = state.api_keys.label(key)?;
+            Some((fingerprint(key), name))
+        });
+        let mut usage = state.usage.start(api_key, model, stream, prompt);
+
+        let upstream = match send_with_failover(state, &client.candidates, prompt, model).await {
+            Ok(upstream) => upstream,
+            Err(err) => {
+                usage.finish(err.status_and_body().0.as_u16());
+                return Err(err);
+            }
+        };
+        usage.set_token_fingerprint(fingerprint(upstream.token()));
+        // 上游已接受请求，此时才计入 API key 的当日请求数
+        let stream_guard = admission.commit();
+        Ok(Self {
+            upstream,
+            stream_guard,
+            usage,
+        })
+    }
+
+    pub fn tokens(&self) -> TokenCounts {
+        self.usage.tokens()
+    }
+
+    fn fail(self, err: ApiError) -> ApiError {
+        self.usage.finish(err.status_and_body().0.as_u16());
+        err
+    }
+
+    // 读取完整的回复，遇到停止条件时提前结束
+    pub async fn collect(mut self, limits: OutputLimits) -> Result<CompletionOutput, ApiError> {
+        let mut output = LimitedOutput::new(limits);
+        let mut text = String::new();
+        while !output.is_finished() {
+            match self.upstream.next().await {
+                Ok(Some(StreamMessage::Text(delta))) => text.push_str(&output.push(&delta)),
+                Ok(Some(StreamMessage::Error(err))) => {
+                    tracing::error!("上游返回错误: {}", err);
+                    return Err(self.fail(ApiError::Upstream(err)));
+                }
+                Ok(Some(StreamMessage::End)) | Ok(None) => break,
+                Err(err) => return Err(self.fail(err)),
+            }
+        }
+        text.push_str(&output.flush());
+        self.usage.add_completion(&text);
+        let tokens = self.usage.tokens();
+        self.usage.finish(StatusCode::OK.as_u16());
+        Ok(CompletionOutput {
+            text,
+            finish_reason: output.finish_reason(),
+            tokens,
+        })
+    }
+
+    // 边读取上游边转发，通道写满时会暂停读取上游；客户端断开后丢弃上游响应以中止请求
+    pub fn stream<F: StreamFormat>(
+        self,
+        limits: OutputLimits,
+        mut format: F,
+    ) -> impl Stream<Item = F::Item> + Send {
+        let (tx, mut rx) = mpsc::channel::<F::Item>(100);
+        let Self {
+            mut upstream,
+            stream_guard,
+            mut usage,
+        } = self;
+
+        tokio::spawn(async move {
+            // 任务结束时释放并发名额
+            let _stream_guard = stream_guard;
+            let mut output = LimitedOutput::new(limits);
+            // 转发结束后按最终状态记录用量
+            let status = async {
+                if !send_all(&tx, format.start()).await {
+                    record_cancellation(format.id());
+                    return CLIENT_CLOSED_REQUEST;
+                }
+
+                let mut error = None;
+                while !output.is_finished() {
+                    let message = tokio::select! {
+                        _ = tx.closed() => {
+                            record_cancellation(format.id());
+                            return CLIENT_CLOSED_REQUEST;
+                        }
+                        message = upstream.next() => message,
+                    };
+                    let text = match message {
+                        Ok(Some(StreamMessage::Text(text))) => output.push(&text),
+                        Ok(Some(StreamMessage::Error(err))) => {
+                            tracing::error!("上游返回错误: {}", err);
+                            error = Some(ApiError::Upstream(err));
+                            break;
+                        }
+                        Ok(Some(StreamMessage::End)) | Ok(None) => break,
+                        Err(err) => {
+                            error = Some(err);
+                            break;
+                        }
+                    };
+
+                    // 只在文本非空时发送
+                    if !text.is_empty() {
+                        usage.add_completion(&text);
+                        if tx.send(format.delta(&text)).await.is_err() {
+                            record_cancellation(format.id());
+                            return CLIENT_CLOSED_REQUEST;
+                        }
+                    }
+                }
+
+                if let Some(err) = error {
+                    if !send_all(&tx, format.error(&err)).await {
+                        record_cancellation(format.id());
+                        return CLIENT_CLOSED_REQUEST;
+                    }
+                    return err.status_and_body().0.as_u16();
+                }
+
+                let rest = output.flush();
+                let mut items = Vec::new();
+                if !rest.is_empty() {
+                    usage.add_completion(&rest);
+                    items.push(format.delta(&rest));
+                }
+                items.extend(format.finish(&output.finish_reason(), usage.tokens()));
+                let _ = send_all(&tx, items).await;
+                StatusCode::OK.as_u16()
+            }
+            .await;
+            usage.finish(status);
+        });
+
+        futures::stream::poll_fn(move |cx| rx.poll_recv(cx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::ApiKeys;
+    use crate::upstream::{send, spawn_stalled_upstream};
+    use crate::usage::UsageRecorder;
+    use futures::StreamExt;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn limited(stop: &[&str], max_tokens: Option<u64>) -> LimitedOutput {
+        LimitedOutput::new(OutputLimits {
+            stop: stop.iter().map(|s| s.to_string()).collect(),
+            max_tokens,
+        })
+    }
+
+    #[test]
+    fn test_stop_sequence_across_chunks() {
+        let mut output = limited(&["\n\nHuman:", ""], None);
+        assert_eq!(output.push("Hello\n"), "Hello");
+        assert_eq!(output.push("\nHum"), "");
+        assert_eq!(output.push("an: next"), "");
+        assert_eq!(
+            output.finish_reason(),
+            FinishReason::StopSequence("\n\nHuman:".to_string())
+        );
+        assert_eq!(output.push("more"), "");
+        assert_eq!(output.flush(), "");
+
+        // 暂存的文本不是停止序列时照常输出
+        let mut output = limited(&["END"], None);
+        assert_eq!(output.push("你好E"), "你好");
+        assert_eq!(output.push("x"), "Ex");
+        assert_eq!(output.push("EN"), "");
+        assert_eq!(output.flush(), "EN");
+        assert_eq!(output.finish_reason(), FinishReason::Stop);
+    }
+
+    #[test]
+    fn test_max_tokens() {
+        let mut output = limited(&[], Some(3));
+        assert_eq!(output.push("abcdefgh"), "abcdefgh");
+        assert!(!output.is_finished());
+        assert_eq!(output.push("你好"), "你");
+        assert_eq!(output.finish_reason(), FinishReason::Length);
+        assert_eq!(output.push("abc"), "");
+    }
+
+    #[tokio::test]
+    async fn test_client_disconnect_cancels_upstream() {
+        let (base_url, mut upstream_closed) = spawn_stalled_upstream("Hello").await;
+        let mut state = AppState::for_test(Vec::new(), ApiKeys::new([]), &base_url);
+        let (recorder, records) = UsageRecorder::capture();
+        state.usage = recorder;
+        let state = Arc::new(state);
+        let before = cancelled_streams();
+
+        let response = send(
+            &state,
+            "POST",
+            "/v1/chat/completions",
+            Some("Bearer token"),
+            Some(serde_json::json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "stream": true,
+            })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().into_data_stream();
+        let first = tokio::time::timeout(Duration::from_secs(5), body.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&first).contains("Hello"));
+
+        // 客户端断开后上游连接随之关闭
+        drop(body);
+        tokio::time::timeout(Duration::from_secs(5), upstream_closed.recv())
+            .await
+            .unwrap();
+        assert!(cancelled_streams() > before);
+        let record = records.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(record.status, CLIENT_CLOSED_REQUEST);
+        assert_eq!(record.completion_chars, 5);
+    }
+
+    #[tokio::test]
+    async fn test_client_disconnect_records_usage_without_stream() {
+        let (base_url, mut upstream_closed) = spawn_stalled_upstream("Hello").await;
+        let mut state = AppState::for_test(Vec::new(), ApiKeys::new([]), &base_url);
+        let (recorder, records) = UsageRecorder::capture();
+        state.usage = recorder;
+        let state = Arc::new(state);
+
+        // 上游不结束，客户端等待超时后放弃请求
+        let request = send(
+            &state,
+            "POST",
+            "/v1/chat/completions",
+            Some("Bearer token"),
+            Some(serde_json::json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "Hi"}],
+            })),
+        );
+        assert!(tokio::time::timeout(Duration::from_millis(200), request)
+            .await
+            .is_err());
+        tokio::time::timeout(Duration::from_secs(5), upstream_closed.recv())
+            .await
+            .unwrap();
+        let record = records.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(record.status, CLIENT_CLOSED_REQUEST);
+        assert!(!record.stream);
+    }
+}
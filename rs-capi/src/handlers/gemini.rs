@@ -0,0 +1,309 @@
+use crate::handlers::completion::{
+    read_json, Completion, FinishReason, OutputLimits, StreamFormat,
+};
+use crate::models::error::ApiError;
+use crate::models::gemini::GenerateContentRequest;
+use crate::state::AppState;
+use crate::usage::TokenCounts;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// 以 Gemini 格式输出的错误：{"error": {"code": ..., "message": ..., "status": ...}}
+pub struct GeminiError(ApiError);
+
+impl From<ApiError> for GeminiError {
+    fn from(error: ApiError) -> Self {
+        Self(error)
+    }
+}
+
+// 按状态码对应 Google API 的错误状态
+fn error_status(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "INVALID_ARGUMENT",
+        StatusCode::UNAUTHORIZED => "UNAUTHENTICATED",
+        StatusCode::FORBIDDEN => "PERMISSION_DENIED",
+        StatusCode::NOT_FOUND => "NOT_FOUND",
+        StatusCode::PAYMENT_REQUIRED | StatusCode::TOO_MANY_REQUESTS => "RESOURCE_EXHAUSTED",
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => "UNAVAILABLE",
+        _ => "INTERNAL",
+    }
+}
+
+fn error_body(error: &ApiError) -> (StatusCode, serde_json::Value) {
+    let (status, body) = error.status_and_body();
+    (
+        status,
+        serde_json::json!({
+            "error": {
+                "code": status.as_u16(),
+                "message": body.error.message,
+                "status": error_status(status),
+            },
+        }),
+    )
+}
+
+impl IntoResponse for GeminiError {
+    fn into_response(self) -> Response {
+        let (status, body) = error_body(&self.0);
+        (status, Json(body)).into_response()
+    }
+}
+
+fn finish_reason(reason: &FinishReason) -> &'static str {
+    match reason {
+        FinishReason::Stop | FinishReason::StopSequence(_) => "STOP",
+        FinishReason::Length => "MAX_TOKENS",
+    }
+}
+
+// 一个 GenerateContentResponse，流式响应中每段文本各一个
+fn response_chunk(
+    id: &str,
+    model: &str,
+    text: &str,
+    finish: Option<(&FinishReason, TokenCounts)>,
+) -> serde_json::Value {
+    let mut candidate = serde_json::json!({
+        "content": {"parts": [{"text": text}], "role": "model"},
+        "index": 0,
+    });
+    let mut chunk = serde_json::json!({
+        "modelVersion": model,
+        "responseId": id,
+    });
+    if let Some((reason, tokens)) = finish {
+        candidate["finishReason"] = finish_reason(reason).into();
+        chunk["usageMetadata"] = serde_json::json!({
+            "promptTokenCount": tokens.prompt,
+            "candidatesTokenCount": tokens.completion,
+            "totalTokenCount": tokens.prompt + tokens.completion,
+        });
+    }
+    chunk["candidates"] = serde_json::json!([candidate]);
+    chunk
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeminiQuery {
+    // 流式请求为 sse 时按 SSE 输出，否则输出逐步写出的 JSON 数组
+    alt: Option<String>,
+    // Gemini 客户端可以通过查询参数传递 key
+    key: Option<String>,
+}
+
+// 处理 /v1beta/models/{model}:generateContent 与 :streamGenerateContent
+pub async fn generate_content(
+    State(state): State<Arc<AppState>>,
+    Path(target): Path<String>,
+    Query(query): Query<GeminiQuery>,
+    mut headers: HeaderMap,
+    request: Request<Body>,
+) -> Result<Response, GeminiError> {
+    let Some((model, method)) = target.rsplit_once(':') else {
+        return Err(ApiError::InvalidRequest {
+            message: format!("缺少方法名: {}", target),
+            param: "model".to_string(),
+        }
+        .into());
+    };
+    let stream = match method {
+        "generateContent" => false,
+        "streamGenerateContent" => true,
+        _ => {
+            return Err(ApiError::InvalidRequest {
+                message: format!("不支持的方法: {}", method),
+                param: "model".to_string(),
+            }
+            .into())
+        }
+    };
+    if let Some(value) = query.key.and_then(|key| HeaderValue::from_str(&key).ok()) {
+        headers.entry("x-goog-api-key").or_insert(value);
+    }
+
+    let request: GenerateContentRequest = read_json(request).await?;
+    tracing::info!(model = %model, stream, "generate_content_request");
+
+    // 与 chat/completions 相同的 role:content 格式，systemInstruction 放在最前
+    let formatted_messages = request
+        .system_instruction
+        .iter()
+        .map(|system| format!("system:{}", system))
+        .chain(
+            request
+                .contents
+                .iter()
+                .map(|content| format!("{}:{}", content.role(), content)),
+        )
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let completion =
+        Completion::start(&state, &headers, model, stream, &formatted_messages).await?;
+    let limits = OutputLimits {
+        stop: request.generation_config.stop_sequences,
+        max_tokens: request.generation_config.max_output_tokens,
+    };
+    let id = Uuid::new_v4().simple().to_string();
+
+    if stream {
+        let sse = query.alt.as_deref() == Some("sse");
+        let format = GeminiStreamFormat {
+            id,
+            model: model.to_string(),
+            sse,
+            sent: 0,
+        };
+        let content_type = if sse {
+            "text/event-stream"
+        } else {
+            "application/json"
+        };
+        return Ok((
+            [(header::CONTENT_TYPE, content_type)],
+            Body::from_stream(completion.stream(limits, format)),
+        )
+            .into_response());
+    }
+
+    let output = completion.collect(limits).await?;
+    Ok(Json(response_chunk(
+        &id,
+        model,
+        &output.text,
+        Some((&output.finish_reason, output.tokens)),
+    ))
+    .into_response())
+}
+
+// Gemini 的流式输出：alt=sse 时每个响应一个 data 事件，否则为逐步写出的 JSON 数组
+struct GeminiStreamFormat {
+    id: String,
+    model: String,
+    sse: bool,
+    // 已发送的响应数，用于输出数组的分隔符
+    sent: usize,
+}
+
+impl GeminiStreamFormat {
+    fn frame(&mut self, chunk: serde_json::Value) -> Result<Bytes, Infallible> {
+        let framed = if self.sse {
+            format!("data: {}\r\n\r\n", chunk)
+        } else {
+            let separator = if self.sent == 0 { "[" } else { ",\r\n" };
+            format!("{}{}", separator, chunk)
+        };
+        self.sent += 1;
+        Ok(Bytes::from(framed))
+    }
+
+    fn close(&self) -> Vec<Result<Bytes, Infallible>> {
+        if self.sse {
+            Vec::new()
+        } else {
+            vec![Ok(Bytes::from_static(b"]"))]
+        }
+    }
+}
+
+impl StreamFormat for GeminiStreamFormat {
+    type Item = Result<Bytes, Infallible>;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn delta(&mut self, text: &str) -> Self::Item {
+        let chunk = response_chunk(&self.id, &self.model, text, None);
+        self.frame(chunk)
+    }
+
+    // 最后一个响应带上结束原因和用量
+    fn finish(&mut self, reason: &FinishReason, tokens: TokenCounts) -> Vec<Self::Item> {
+        let chunk = response_chunk(&self.id, &self.model, "", Some((reason, tokens)));
+        let mut items = vec![self.frame(chunk)];
+        items.extend(self.close());
+        items
+    }
+
+    fn error(&mut self, error: &ApiError) -> Vec<Self::Item> {
+        let mut items = vec![self.frame(error_body(error).1)];
+        items.extend(self.close());
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upstream::{post_json, test_state};
+
+    #[tokio::test]
+    async fn test_generate_content() {
+        let state = test_state(&["Hello", " world"]).await;
+        let body = serde_json::json!({
+            "contents": [{"role": "user", "parts": [{"text": "Hi"}]}],
+        });
+
+        let response = post_json(
+            &state,
+            "/v1beta/models/gpt-4o:generateContent",
+            body.clone(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(
+            response["candidates"][0]["content"]["parts"][0]["text"],
+            "Hello world"
+        );
+        assert_eq!(response["candidates"][0]["finishReason"], "STOP");
+
+        // 默认输出 JSON 数组
+        let response = post_json(
+            &state,
+            "/v1beta/models/gpt-4o:streamGenerateContent",
+            body.clone(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let chunks: Vec<serde_json::Value> = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(
+            chunks[1]["candidates"][0]["content"]["parts"][0]["text"],
+            " world"
+        );
+        assert!(
+            chunks[2]["usageMetadata"]["totalTokenCount"]
+                .as_u64()
+                .unwrap()
+                > 0
+        );
+
+        let response = post_json(
+            &state,
+            "/v1beta/models/gpt-4o:streamGenerateContent?alt=sse",
+            body.clone(),
+        )
+        .await;
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        assert_eq!(response.body().matches("data: ").count(), 3);
+
+        let response = post_json(&state, "/v1beta/models/gpt-4o:embedContent", body).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(response["error"]["status"], "INVALID_ARGUMENT");
+    }
+}
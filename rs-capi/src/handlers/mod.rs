@@ -1,2 +1,9 @@
+pub mod admin;
+pub mod anthropic;
 pub mod chat;
+pub mod completion;
+pub mod gemini;
 pub mod models;
+pub mod ollama;
+pub mod responses;
+pub mod text_completion;
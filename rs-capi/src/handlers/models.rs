@@ -1,82 +1,40 @@
 use axum::Json;
 
+// 支持的模型：(id, created, owned_by)
+pub const MODELS: &[(&str, i64, &str)] = &[
+    ("claude-3-5-sonnet-20241022", 1713744000, "anthropic"),
+    ("claude-3-opus", 1709251200, "anthropic"),
+    ("claude-3.5-haiku", 1711929600, "anthropic"),
+    ("claude-3.5-sonnet", 1711929600, "anthropic"),
+    ("cursor-small", 1712534400, "cursor"),
+    ("gpt-3.5-turbo", 1677649200, "openai"),
+    ("gpt-4", 1687392000, "openai"),
+    ("gpt-4-turbo-2024-04-09", 1712620800, "openai"),
+    ("gpt-4o", 1712620800, "openai"),
+    ("gpt-4o-mini", 1712620800, "openai"),
+    ("o1-mini", 1712620800, "openai"),
+    ("o1-preview", 1712620800, "openai"),
+];
+
+pub fn is_known_model(model: &str) -> bool {
+    MODELS.iter().any(|(id, _, _)| *id == model)
+}
+
 // 处理模型列表请求
 pub async fn models() -> Json<serde_json::Value> {
+    let data: Vec<_> = MODELS
+        .iter()
+        .map(|(id, created, owned_by)| {
+            serde_json::json!({
+                "id": id,
+                "object": "model",
+                "created": created,
+                "owned_by": owned_by
+            })
+        })
+        .collect();
     Json(serde_json::json!({
         "object": "list",
-        "data": [
-            {
-                "id": "claude-3-5-sonnet-20241022",
-                "object": "model",
-                "created": 1713744000,
-                "owned_by": "anthropic"
-            },
-            {
-                "id": "claude-3-opus",
-                "object": "model",
-                "created": 1709251200,
-                "owned_by": "anthropic"
-            },
-            {
-                "id": "claude-3.5-haiku",
-                "object": "model",
-                "created": 1711929600,
-                "owned_by": "anthropic"
-            },
-            {
-                "id": "claude-3.5-sonnet",
-                "object": "model",
-                "created": 1711929600,
-                "owned_by": "anthropic"
-            },
-            {
-                "id": "cursor-small",
-                "object": "model",
-                "created": 1712534400,
-                "owned_by": "cursor"
-            },
-            {
-                "id": "gpt-3.5-turbo",
-                "object": "model",
-                "created": 1677649200,
-                "owned_by": "openai"
-            },
-            {
-                "id": "gpt-4",
-                "object": "model",
-                "created": 1687392000,
-                "owned_by": "openai"
-            },
-            {
-                "id": "gpt-4-turbo-2024-04-09",
-                "object": "model",
-                "created": 1712620800,
-                "owned_by": "openai"
-            },
-            {
-                "id": "gpt-4o",
-                "object": "model",
-                "created": 1712620800,
-                "owned_by": "openai"
-            },
-            {
-                "id": "gpt-4o-mini",
-                "object": "model",
-                "created": 1712620800,
-                "owned_by": "openai"
-            },
-            {
-                "id": "o1-mini",
-                "object": "model",
-                "created": 1712620800,
-                "owned_by": "openai"
-            },
-            {
-                "id": "o1-preview",
-                "object": "model",
-                "created": 1712620800,
-                "owned_by": "openai"
-            }
-        ]
+        "data": data
     }))
 }
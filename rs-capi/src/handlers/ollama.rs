@@ -0,0 +1,357 @@
+use crate::auth::authenticate;
+use crate::handlers::completion::{
+    read_json, Completion, FinishReason, OutputLimits, StreamFormat,
+};
+use crate::handlers::models::MODELS;
+use crate::models::error::ApiError;
+use crate::models::ollama::{with_images, ChatRequest, GenerateRequest, Options};
+use crate::state::AppState;
+use crate::usage::TokenCounts;
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+
+// 以 Ollama 格式输出的错误：{"error": "..."}
+pub struct OllamaError(ApiError);
+
+impl From<ApiError> for OllamaError {
+    fn from(error: ApiError) -> Self {
+        Self(error)
+    }
+}
+
+fn error_body(error: &ApiError) -> serde_json::Value {
+    serde_json::json!({ "error": error.status_and_body().1.error.message })
+}
+
+impl IntoResponse for OllamaError {
+    fn into_response(self) -> Response {
+        let status = self.0.status_and_body().0;
+        (status, Json(error_body(&self.0))).into_response()
+    }
+}
+
+// Ollama 客户端会在模型名后附加 :latest 标签
+fn cursor_model(model: &str) -> &str {
+    model.strip_suffix(":latest").unwrap_or(model)
+}
+
+// 列出模型，与 /v1/models 使用同一份列表
+pub async fn tags() -> Json<serde_json::Value> {
+    let models: Vec<_> = MODELS
+        .iter()
+        .map(|(id, created, owned_by)| {
+            let modified_at = DateTime::from_timestamp(*created, 0)
+                .unwrap_or_default()
+                .to_rfc3339_opts(SecondsFormat::Secs, true);
+            serde_json::json!({
+                "name": id,
+                "model": id,
+                "modified_at": modified_at,
+                "size": 0,
+                "digest": format!("{:x}", Sha256::digest(id.as_bytes())),
+                "details": {
+                    "format": "",
+                    "family": owned_by,
+                    "families": null,
+                    "parameter_size": "",
+                    "quantization_level": "",
+                },
+            })
+        })
+        .collect();
+    Json(serde_json::json!({ "models": models }))
+}
+
+// 处理 /api/chat
+pub async fn chat(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    request: Request<Body>,
+) -> Result<Response, OllamaError> {
+    let started = Instant::now();
+    let request: ChatRequest = read_json(request).await?;
+    tracing::info!(model = %request.model, stream = request.stream, "ollama_chat_request");
+
+    // 与 chat/completions 相同的 role:content 格式
+    let formatted_messages = request
+        .messages
+        .iter()
+        .map(|msg| format!("{}:{}", msg.role, with_images(&msg.content, &msg.images)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let reply = Reply {
+        model: request.model,
+        endpoint: Endpoint::Chat,
+        started,
+        first_token: None,
+    };
+    respond(
+        &state,
+        &headers,
+        reply,
+        request.stream,
+        &formatted_messages,
+        request.options,
+    )
+    .await
+}
+
+// 处理 /api/generate，prompt 作为一条 user 消息发往上游
+pub async fn generate(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    request: Request<Body>,
+) -> Result<Response, OllamaError> {
+    let started = Instant::now();
+    let request: GenerateRequest = read_json(request).await?;
+    tracing::info!(model = %request.model, stream = request.stream, "ollama_generate_request");
+
+    // 客户端用空 prompt 预加载模型，与 Ollama 一样直接返回，不请求上游也不计入用量
+    if request.prompt.is_empty() && request.system.as_deref().unwrap_or_default().is_empty() {
+        authenticate(&state, &headers)?;
+        return Ok(Json(serde_json::json!({
+            "model": request.model,
+            "created_at": Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true),
+            "response": "",
+            "done": true,
+            "done_reason": "load",
+        }))
+        .into_response());
+    }
+
+    let formatted_messages = request
+        .system
+        .iter()
+        .map(|system| format!("system:{}", system))
+        .chain(std::iter::once(format!(
+            "user:{}",
+            with_images(&request.prompt, &request.images)
+        )))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let reply = Reply {
+        model: request.model,
+        endpoint: Endpoint::Generate,
+        started,
+        first_token: None,
+    };
+    respond(
+        &state,
+        &headers,
+        reply,
+        request.stream,
+        &formatted_messages,
+        request.options,
+    )
+    .await
+}
+
+async fn respond(
+    state: &AppState,
+    headers: &HeaderMap,
+    reply: Reply,
+    stream: bool,
+    prompt: &str,
+    options: Options,
+) -> Result<Response, OllamaError> {
+    let completion =
+        Completion::start(state, headers, cursor_model(&reply.model), stream, prompt).await?;
+    let limits = OutputLimits {
+        max_tokens: options.max_tokens(),
+        stop: options.stop,
+    };
+
+    if stream {
+        let format = NdjsonFormat {
+            id: Uuid::new_v4().to_string(),
+            reply,
+        };
+        return Ok((
+            [(header::CONTENT_TYPE, "application/x-ndjson")],
+            Body::from_stream(completion.stream(limits, format)),
+        )
+            .into_response());
+    }
+
+    let output = completion.collect(limits).await?;
+    Ok(
+        Json(reply.chunk(&output.text, Some((&output.finish_reason, output.tokens))))
+            .into_response(),
+    )
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Endpoint {
+    Chat,
+    Generate,
+}
+
+// 构造 /api/chat 与 /api/generate 的响应对象
+struct Reply {
+    model: String,
+    endpoint: Endpoint,
+    // 收到请求的时间，用于统计耗时
+    started: Instant,
+    first_token: Option<Instant>,
+}
+
+impl Reply {
+    fn chunk(&self, text: &str, done: Option<(&FinishReason, TokenCounts)>) -> serde_json::Value {
+        let mut chunk = serde_json::json!({
+            "model": self.model,
+            "created_at": Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true),
+        });
+        match self.endpoint {
+            Endpoint::Chat => {
+                chunk["message"] = serde_json::json!({"role": "assistant", "content": text})
+            }
+            Endpoint::Generate => chunk["response"] = text.into(),
+        }
+        chunk["done"] = done.is_some().into();
+        if let Some((reason, tokens)) = done {
+            // 耗时以纳秒为单位；首段文本之前计为提示处理时间
+            let now = Instant::now();
+            let first_token = self.first_token.unwrap_or(self.started);
+            chunk["done_reason"] = match reason {
+                FinishReason::Stop | FinishReason::StopSequence(_) => "stop",
+                FinishReason::Length => "length",
+            }
+            .into();
+            chunk["total_duration"] = (now.duration_since(self.started).as_nanos() as u64).into();
+            chunk["load_duration"] = 0.into();
+            chunk["prompt_eval_count"] = tokens.prompt.into();
+            chunk["prompt_eval_duration"] =
+                (first_token.duration_since(self.started).as_nanos() as u64).into();
+            chunk["eval_count"] = tokens.completion.into();
+            chunk["eval_duration"] = (now.duration_since(first_token).as_nanos() as u64).into();
+        }
+        chunk
+    }
+}
+
+// Ollama 的流式输出：每行一个 JSON 对象，最后一行 done 为 true 并带上统计信息
+struct NdjsonFormat {
+    id: String,
+    reply: Reply,
+}
+
+fn line(value: serde_json::Value) -> Result<Bytes, Infallible> {
+    Ok(Bytes::from(format!("{}\n", value)))
+}
+
+impl StreamFormat for NdjsonFormat {
+    type Item = Result<Bytes, Infallible>;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn delta(&mut self, text: &str) -> Self::Item {
+        self.reply.first_token.get_or_insert_with(Instant::now);
+        line(self.reply.chunk(text, None))
+    }
+
+    fn finish(&mut self, reason: &FinishReason, tokens: TokenCounts) -> Vec<Self::Item> {
+        vec![line(self.reply.chunk("", Some((reason, tokens))))]
+    }
+
+    fn error(&mut self, error: &ApiError) -> Vec<Self::Item> {
+        vec![line(error_body(error))]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upstream::{post_json, test_state};
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn test_tags() {
+        let Json(tags) = tags().await;
+        let models = tags["models"].as_array().unwrap();
+        assert_eq!(models.len(), MODELS.len());
+        assert!(models.iter().any(|model| model["name"] == "gpt-4o"));
+        assert_eq!(models[0]["digest"].as_str().unwrap().len(), 64);
+    }
+
+    #[tokio::test]
+    async fn test_chat_and_generate() {
+        let state = test_state(&["Hello", " world"]).await;
+
+        // 默认流式输出，每行一个 JSON
+        let response = post_json(
+            &state,
+            "/api/chat",
+            serde_json::json!({
+                "model": "gpt-4o:latest",
+                "messages": [{"role": "user", "content": "Hi"}],
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let lines: Vec<serde_json::Value> = response
+            .body()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["message"]["content"], "Hello");
+        assert_eq!(lines[0]["done"], false);
+        assert_eq!(lines[2]["done"], true);
+        assert_eq!(lines[2]["done_reason"], "stop");
+        assert_eq!(lines[2]["model"], "gpt-4o:latest");
+
+        let response = post_json(
+            &state,
+            "/api/generate",
+            serde_json::json!({
+                "model": "gpt-4o",
+                "prompt": "Hi",
+                "stream": false,
+                "options": {"stop": [" wor"]},
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let reply: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(reply["response"], "Hello");
+        assert_eq!(reply["done"], true);
+        assert!(reply["eval_count"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_generate_load() {
+        // 上游地址无效，请求上游会失败
+        let state = Arc::new(AppState::for_test(
+            Vec::new(),
+            crate::auth::ApiKeys::new([]),
+            "http://127.0.0.1:1",
+        ));
+
+        let response = post_json(
+            &state,
+            "/api/generate",
+            serde_json::json!({"model": "gpt-4o"}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let reply: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(reply["response"], "");
+        assert_eq!(reply["done"], true);
+        assert_eq!(reply["done_reason"], "load");
+    }
+}
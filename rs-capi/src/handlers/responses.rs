@@ -0,0 +1,424 @@
+use crate::auth::authenticate;
+use crate::handlers::completion::{
+    read_json, Completion, FinishReason, OutputLimits, StreamFormat,
+};
+use crate::models::error::ApiError;
+use crate::models::responses::ResponsesRequest;
+use crate::response_store::Turn;
+use crate::state::AppState;
+use crate::usage::TokenCounts;
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::convert::Infallible;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// 处理 OpenAI Responses 请求，错误沿用 OpenAI 格式
+pub async fn create_response(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    request: Request<Body>,
+) -> Result<Response, ApiError> {
+    let request: ResponsesRequest = read_json(request).await?;
+    tracing::info!(
+        model = %request.model,
+        stream = request.stream,
+        previous_response_id = ?request.previous_response_id,
+        "responses_request"
+    );
+
+    // 先认证，保存的对话只对创建它的客户端可见
+    let client = authenticate(&state, &headers)?;
+    let owner = client.owner.clone();
+
+    // 续接时在之前的对话后追加本次输入
+    let mut turns = match &request.previous_response_id {
+        Some(id) => state
+            .responses
+            .get(id, &owner)
+            .ok_or_else(|| ApiError::InvalidRequest {
+                message: format!("未找到 previous_response_id 对应的响应: {}", id),
+                param: "previous_response_id".to_string(),
+            })?,
+        None => Vec::new(),
+    };
+    turns.extend(request.input.turns());
+
+    // 与 chat/completions 相同的 role:content 格式，instructions 放在最前
+    let formatted_messages = request
+        .instructions
+        .iter()
+        .map(|instructions| format!("system:{}", instructions))
+        .chain(
+            turns
+                .iter()
+                .map(|turn| format!("{}:{}", turn.role, turn.content)),
+        )
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let completion = Completion::start_with(
+        &state,
+        client,
+        &request.model,
+        request.stream,
+        &formatted_messages,
+    )
+    .await?;
+    let limits = OutputLimits {
+        stop: Vec::new(),
+        max_tokens: request.max_output_tokens,
+    };
+    let reply = Reply {
+        id: format!("resp_{}", Uuid::new_v4().simple()),
+        message_id: format!("msg_{}", Uuid::new_v4().simple()),
+        model: request.model,
+        created_at: chrono::Utc::now().timestamp(),
+        instructions: request.instructions,
+        previous_response_id: request.previous_response_id,
+        max_output_tokens: request.max_output_tokens,
+        store: request.store,
+        owner,
+    };
+
+    if request.stream {
+        let format = ResponseEventFormat {
+            state: state.clone(),
+            reply,
+            turns,
+            text: String::new(),
+            sequence_number: 0,
+        };
+        let stream = completion.stream(limits, format);
+        return Ok(Sse::new(stream).into_response());
+    }
+
+    let output = completion.collect(limits).await?;
+    reply.save(&state, turns, &output.text);
+    Ok(
+        Json(reply.body(Some((&output.text, &output.finish_reason, output.tokens))))
+            .into_response(),
+    )
+}
+
+// 构造 Response 对象，输出只有一条助手消息
+struct Reply {
+    id: String,
+    message_id: String,
+    model: String,
+    created_at: i64,
+    instructions: Option<String>,
+    previous_response_id: Option<String>,
+    max_output_tokens: Option<u64>,
+    store: bool,
+    owner: String,
+}
+
+impl Reply {
+    // 保存本轮对话，之后可以用本响应的 id 续接
+    fn save(&self, state: &AppState, mut turns: Vec<Turn>, text: &str) {
+        if self.store {
+            turns.push(Turn::new("assistant", text));
+            state
+                .responses
+                .insert(self.id.clone(), self.owner.clone(), turns);
+        }
+    }
+
+    fn output_text(text: &str) -> serde_json::Value {
+        serde_json::json!({"type": "output_text", "text": text, "annotations": []})
+    }
+
+    fn message(&self, content: Vec<serde_json::Value>, status: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": "message",
+            "id": self.message_id,
+            "status": status,
+            "role": "assistant",
+            "content": content,
+        })
+    }
+
+    // output 为 None 时表示仍在生成
+    fn body(&self, output: Option<(&str, &FinishReason, TokenCounts)>) -> serde_json::Value {
+        let (status, incomplete_details, output, usage) = match output {
+            None => (
+                "in_progress",
+                serde_json::Value::Null,
+                Vec::new(),
+                serde_json::Value::Null,
+            ),
+            Some((text, reason, tokens)) => {
+                let (status, incomplete_details) = match reason {
+                    FinishReason::Length => (
+                        "incomplete",
+                        serde_json::json!({"reason": "max_output_tokens"}),
+                    ),
+                    FinishReason::Stop | FinishReason::StopSequence(_) => {
+                        ("completed", serde_json::Value::Null)
+                    }
+                };
+                let usage = serde_json::json!({
+                    "input_tokens": tokens.prompt,
+                    "output_tokens": tokens.completion,
+                    "total_tokens": tokens.prompt + tokens.completion,
+                });
+                let message = self.message(vec![Self::output_text(text)], status);
+                (status, incomplete_details, vec![message], usage)
+            }
+        };
+        serde_json::json!({
+            "id": self.id,
+            "object": "response",
+            "created_at": self.created_at,
+            "status": status,
+            "error": null,
+            "incomplete_details": incomplete_details,
+            "instructions": self.instructions,
+            "max_output_tokens": self.max_output_tokens,
+            "model": self.model,
+            "output": output,
+            "previous_response_id": self.previous_response_id,
+            "store": self.store,
+            "usage": usage,
+        })
+    }
+}
+
+// Responses 的流式事件序列：response.created、response.in_progress、output_item 与 content_part
+// 的 added 事件、若干 response.output_text.delta，然后是对应的 done 事件和 response.completed
+struct ResponseEventFormat {
+    state: Arc<AppState>,
+    reply: Reply,
+    // 本次请求的完整对话，结束时连同回复一起保存
+    turns: Vec<Turn>,
+    // 已输出的文本
+    text: String,
+    sequence_number: u64,
+}
+
+impl ResponseEventFormat {
+    fn event(&mut self, name: &str, mut data: serde_json::Value) -> Result<Event, Infallible> {
+        data["type"] = name.into();
+        data["sequence_number"] = self.sequence_number.into();
+        self.sequence_number += 1;
+        Ok(Event::default().event(name).data(data.to_string()))
+    }
+}
+
+impl StreamFormat for ResponseEventFormat {
+    type Item = Result<Event, Infallible>;
+
+    fn id(&self) -> &str {
+        &self.reply.id
+    }
+
+    fn start(&mut self) -> Vec<Self::Item> {
+        let response = self.reply.body(None);
+        let item = self.reply.message(Vec::new(), "in_progress");
+        let part = Reply::output_text("");
+        let message_id = self.reply.message_id.clone();
+        vec![
+            self.event(
+                "response.created",
+                serde_json::json!({"response": response}),
+            ),
+            self.event(
+                "response.in_progress",
+                serde_json::json!({"response": response}),
+            ),
+            self.event(
+                "response.output_item.added",
+                serde_json::json!({"output_index": 0, "item": item}),
+            ),
+            self.event(
+                "response.content_part.added",
+                serde_json::json!({
+                    "item_id": message_id,
+                    "output_index": 0,
+                    "content_index": 0,
+                    "part": part,
+                }),
+            ),
+        ]
+    }
+
+    fn delta(&mut self, text: &str) -> Self::Item {
+        self.text.push_str(text);
+        let message_id = self.reply.message_id.clone();
+        self.event(
+            "response.output_text.delta",
+            serde_json::json!({
+                "item_id": message_id,
+                "output_index": 0,
+                "content_index": 0,
+                "delta": text,
+            }),
+        )
+    }
+
+    fn finish(&mut self, reason: &FinishReason, tokens: TokenCounts) -> Vec<Self::Item> {
+        let text = std::mem::take(&mut self.text);
+        self.reply
+            .save(&self.state, std::mem::take(&mut self.turns), &text);
+
+        let response = self.reply.body(Some((&text, reason, tokens)));
+        let item = response["output"][0].clone();
+        let done = match reason {
+            FinishReason::Length => "response.incomplete",
+            FinishReason::Stop | FinishReason::StopSequence(_) => "response.completed",
+        };
+        let message_id = self.reply.message_id.clone();
+        vec![
+            self.event(
+                "response.output_text.done",
+                serde_json::json!({
+                    "item_id": message_id,
+                    "output_index": 0,
+                    "content_index": 0,
+                    "text": text,
+                }),
+            ),
+            self.event(
+                "response.content_part.done",
+                serde_json::json!({
+                    "item_id": message_id,
+                    "output_index": 0,
+                    "content_index": 0,
+                    "part": Reply::output_text(&text),
+                }),
+            ),
+            self.event(
+                "response.output_item.done",
+                serde_json::json!({"output_index": 0, "item": item}),
+            ),
+            self.event(done, serde_json::json!({"response": response})),
+        ]
+    }
+
+    fn error(&mut self, error: &ApiError) -> Vec<Self::Item> {
+        let detail = error.status_and_body().1.error;
+        vec![self.event(
+            "error",
+            serde_json::json!({
+                "code": detail.code,
+                "message": detail.message,
+                "param": detail.param,
+            }),
+        )]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token_pool::fingerprint;
+    use crate::upstream::{post_json, post_json_as, test_state};
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn test_responses() {
+        let state = test_state(&["Hello", " world"]).await;
+
+        let response = post_json(
+            &state,
+            "/v1/responses",
+            serde_json::json!({"model": "gpt-4o", "instructions": "Be brief.", "input": "Hi"}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(body["object"], "response");
+        assert_eq!(body["status"], "completed");
+        assert_eq!(body["output"][0]["content"][0]["text"], "Hello world");
+        let id = body["id"].as_str().unwrap();
+        let owner = fingerprint("token");
+        assert_eq!(
+            state.responses.get(id, &owner),
+            Some(vec![
+                Turn::new("user", "Hi"),
+                Turn::new("assistant", "Hello world")
+            ])
+        );
+
+        // 续接之前的对话并流式输出
+        let response = post_json(
+            &state,
+            "/v1/responses",
+            serde_json::json!({
+                "model": "gpt-4o",
+                "input": [{"role": "user", "content": "Again"}],
+                "previous_response_id": id,
+                "max_output_tokens": 2,
+                "stream": true,
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let events: Vec<&str> = response
+            .body()
+            .lines()
+            .filter_map(|line| line.strip_prefix("event: "))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "response.created",
+                "response.in_progress",
+                "response.output_item.added",
+                "response.content_part.added",
+                "response.output_text.delta",
+                "response.output_text.delta",
+                "response.output_text.done",
+                "response.content_part.done",
+                "response.output_item.done",
+                "response.incomplete"
+            ]
+        );
+        let last: serde_json::Value = serde_json::from_str(
+            response
+                .body()
+                .lines()
+                .rev()
+                .find_map(|line| line.strip_prefix("data: "))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(last["sequence_number"], 9);
+        assert_eq!(last["response"]["previous_response_id"], id);
+        let turns = state
+            .responses
+            .get(last["response"]["id"].as_str().unwrap(), &owner)
+            .unwrap();
+        assert_eq!(turns.len(), 4);
+        assert_eq!(turns[3], Turn::new("assistant", "Hello wo"));
+
+        let response = post_json(
+            &state,
+            "/v1/responses",
+            serde_json::json!({"model": "gpt-4o", "input": "Hi", "previous_response_id": "resp_missing"}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(body["error"]["param"], "previous_response_id");
+
+        // 其他客户端不能续接该对话，未认证的请求先返回 401
+        let continuation =
+            serde_json::json!({"model": "gpt-4o", "input": "Hi", "previous_response_id": id});
+        let response = post_json_as(
+            &state,
+            "/v1/responses",
+            Some("Bearer other"),
+            continuation.clone(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = post_json_as(&state, "/v1/responses", None, continuation).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
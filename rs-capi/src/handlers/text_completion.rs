@@ -0,0 +1,222 @@
+use crate::handlers::completion::{
+    read_json, Completion, FinishReason, OutputLimits, StreamFormat,
+};
+use crate::models::error::ApiError;
+use crate::models::text_completion::{
+    CompletionChoice, CompletionRequest, CompletionResponse, Usage,
+};
+use crate::state::AppState;
+use crate::usage::TokenCounts;
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::convert::Infallible;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn finish_reason(reason: &FinishReason) -> &'static str {
+    match reason {
+        FinishReason::Stop | FinishReason::StopSequence(_) => "stop",
+        FinishReason::Length => "length",
+    }
+}
+
+fn response(
+    id: &str,
+    model: &str,
+    text: String,
+    finish: Option<(&FinishReason, TokenCounts)>,
+) -> CompletionResponse {
+    CompletionResponse {
+        id: id.to_string(),
+        object: "text_completion".to_string(),
+        created: chrono::Utc::now().timestamp(),
+        model: model.to_string(),
+        choices: vec![CompletionChoice {
+            text,
+            index: 0,
+            logprobs: None,
+            finish_reason: finish.map(|(reason, _)| finish_reason(reason).to_string()),
+        }],
+        usage: finish.map(|(_, tokens)| Usage {
+            prompt_tokens: tokens.prompt,
+            completion_tokens: tokens.completion,
+            total_tokens: tokens.prompt + tokens.completion,
+        }),
+    }
+}
+
+// 处理旧版文本补全请求，prompt 作为一条 user 消息发往上游
+pub async fn completions(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    request: Request<Body>,
+) -> Result<Response, ApiError> {
+    let request: CompletionRequest = read_json(request).await?;
+    tracing::info!(
+        model = %request.model,
+        stream = request.stream,
+        "completions_request"
+    );
+
+    let prompt = request.prompt()?;
+    // 上游不支持在中间插入，把 suffix 作为提示附在 prompt 之后
+    let formatted_messages = match &request.suffix {
+        Some(suffix) if !suffix.is_empty() => {
+            format!("user:{}\n[Text after the completion: {}]", prompt, suffix)
+        }
+        _ => format!("user:{}", prompt),
+    };
+
+    let completion = Completion::start(
+        &state,
+        &headers,
+        &request.model,
+        request.stream,
+        &formatted_messages,
+    )
+    .await?;
+    let limits = OutputLimits {
+        stop: request.stop(),
+        max_tokens: request.max_tokens,
+    };
+    let id = format!("cmpl-{}", Uuid::new_v4());
+    let echo = request.echo.then_some(prompt);
+
+    if request.stream {
+        let format = TextCompletionFormat {
+            id,
+            model: request.model,
+            echo,
+        };
+        let stream = completion.stream(limits, format);
+        return Ok(Sse::new(stream).into_response());
+    }
+
+    let output = completion.collect(limits).await?;
+    let text = echo.unwrap_or_default() + &output.text;
+    Ok(Json(response(
+        &id,
+        &request.model,
+        text,
+        Some((&output.finish_reason, output.tokens)),
+    ))
+    .into_response())
+}
+
+// 旧版流式输出：每段文本一个 text_completion 对象，最后一个带 finish_reason 和用量，然后是 [DONE]
+struct TextCompletionFormat {
+    id: String,
+    model: String,
+    // echo 时最先发送的 prompt
+    echo: Option<String>,
+}
+
+impl TextCompletionFormat {
+    fn event(
+        &self,
+        text: String,
+        finish: Option<(&FinishReason, TokenCounts)>,
+    ) -> Result<Event, Infallible> {
+        let chunk = response(&self.id, &self.model, text, finish);
+        Ok(Event::default().data(serde_json::to_string(&chunk).unwrap()))
+    }
+}
+
+impl StreamFormat for TextCompletionFormat {
+    type Item = Result<Event, Infallible>;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn start(&mut self) -> Vec<Self::Item> {
+        match self.echo.take() {
+            Some(prompt) => vec![self.event(prompt, None)],
+            None => Vec::new(),
+        }
+    }
+
+    fn delta(&mut self, text: &str) -> Self::Item {
+        self.event(text.to_string(), None)
+    }
+
+    fn finish(&mut self, reason: &FinishReason, tokens: TokenCounts) -> Vec<Self::Item> {
+        vec![
+            self.event(String::new(), Some((reason, tokens))),
+            Ok(Event::default().data("[DONE]")),
+        ]
+    }
+
+    // 与 chat/completions 一致，在完成标记之前发送错误
+    fn error(&mut self, error: &ApiError) -> Vec<Self::Item> {
+        let (_, body) = error.status_and_body();
+        vec![
+            Ok(Event::default().data(serde_json::to_string(&body).unwrap())),
+            Ok(Event::default().data("[DONE]")),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::upstream::{post_json, test_state};
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn test_completions() {
+        let state = test_state(&["Hello", " world"]).await;
+
+        let response = post_json(
+            &state,
+            "/v1/completions",
+            serde_json::json!({
+                "model": "gpt-3.5-turbo",
+                "prompt": "Say: ",
+                "echo": true,
+                "stop": " wor",
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(body["object"], "text_completion");
+        assert_eq!(body["choices"][0]["text"], "Say: Hello");
+        assert_eq!(body["choices"][0]["finish_reason"], "stop");
+        assert!(body["usage"]["total_tokens"].as_u64().unwrap() > 0);
+
+        let response = post_json(
+            &state,
+            "/v1/completions",
+            serde_json::json!({
+                "model": "gpt-3.5-turbo",
+                "prompt": ["Say: "],
+                "suffix": "!",
+                "max_tokens": 2,
+                "stream": true,
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data: Vec<&str> = response
+            .body()
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect();
+        assert_eq!(data.len(), 4);
+        assert_eq!(data[3], "[DONE]");
+        let chunks: Vec<serde_json::Value> = data[..3]
+            .iter()
+            .map(|chunk| serde_json::from_str(chunk).unwrap())
+            .collect();
+        assert_eq!(chunks[0]["choices"][0]["text"], "Hello");
+        assert_eq!(
+            chunks[0]["choices"][0]["finish_reason"],
+            serde_json::Value::Null
+        );
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "length");
+    }
+}
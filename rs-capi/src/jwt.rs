@@ -0,0 +1,76 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+// WorkosCursorSessionToken 中我们关心的声明
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct TokenClaims {
+    // Cursor 用户 id，如 auth0|user_01...
+    pub sub: Option<String>,
+    // 过期时间（Unix 秒）
+    pub exp: Option<i64>,
+}
+
+impl TokenClaims {
+    // 解析 JWT 的负载部分，不校验签名；不是合法 JWT 时返回 None
+    pub fn decode(token: &str) -> Option<Self> {
+        let mut parts = token.split('.');
+        let (_header, payload, _signature) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() {
+            return None;
+        }
+        // 兼容带填充的 base64url
+        let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+        serde_json::from_slice(&payload).ok()
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.exp?, 0)
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at()
+            .is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+#[cfg(test)]
+pub fn encode_test_token(payload: &serde_json::Value) -> String {
+    format!(
+        "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.{}.signature",
+        URL_SAFE_NO_PAD.encode(payload.to_string())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_claims() {
+        let token = encode_test_token(&serde_json::json!({
+            "sub": "auth0|user_01ABC",
+            "exp": 1893456000,
+            "scope": "openid profile email offline_access"
+        }));
+        let claims = TokenClaims::decode(&token).unwrap();
+        assert_eq!(claims.sub.as_deref(), Some("auth0|user_01ABC"));
+        assert_eq!(
+            claims.expires_at().unwrap().to_rfc3339(),
+            "2030-01-01T00:00:00+00:00"
+        );
+        assert!(!claims.is_expired_at(Utc::now()));
+        assert!(claims.is_expired_at(DateTime::from_timestamp(1893456000, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(TokenClaims::decode("not-a-jwt"), None);
+        assert_eq!(TokenClaims::decode("a.!!!.c"), None);
+        assert_eq!(TokenClaims::decode("a.b.c.d"), None);
+        // 没有 exp 的 token 视为不过期
+        let claims = TokenClaims::decode(&encode_test_token(&serde_json::json!({}))).unwrap();
+        assert!(!claims.is_expired_at(Utc::now()));
+    }
+}
@@ -1,19 +1,56 @@
+mod auth;
+mod checksum;
 mod handlers;
+mod http_client;
+mod jwt;
 mod models;
+mod proto;
+mod quota;
+mod response_store;
+mod state;
+mod token_pool;
+mod upstream;
+mod usage;
 
 use axum::{
-    routing::{get, post},
+    routing::{get, patch, post},
     Router,
 };
 use tower_http::trace::TraceLayer;
 // use http::HeaderName as HttpHeaderName;
+use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
-mod hex_utils;
 
 #[tokio::main]
 async fn main() {
     // 初始化日志
     tracing_subscriber::fmt::init();
+    dotenv::dotenv().ok();
+
+    // 创建共享的出站 HTTP 客户端
+    let http = http_client::HttpClients::new(http_client::HttpClientConfig::from_env())
+        .expect("创建HTTP客户端失败");
+    let state = Arc::new(state::AppState::from_env(http));
+
+    // 定期检查服务端 token 是否即将过期（加载时已检查过一次）
+    let expiry_state = state.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(60 * 60)).await;
+            expiry_state.token_pool.check_expiry();
+        }
+    });
+
+    // 定期查询服务端 token 的账号额度
+    if !state.quota.interval.is_zero() {
+        let quota_state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                quota::refresh_all(&quota_state).await;
+                tokio::time::sleep(quota_state.quota.interval).await;
+            }
+        });
+    }
 
     // 创建CORS中间件
     let cors = CorsLayer::new()
@@ -22,35 +59,27 @@ async fn main() {
         .allow_headers(Any);
 
     // 创建路由
-    let app = Router::new()
-        .route(
-            "/v1/chat/completions",
-            post(handlers::chat::chat_completions),
-        )
-        .route("/models", get(handlers::models::models))
-        .route("/v1/models", get(handlers::models::models))
-        .layer(cors)
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(|request: &axum::http::Request<_>| {
-                    tracing::info_span!(
-                        "http_request",
-                        method = %request.method(),
-                        uri = %request.uri(),
-                    )
-                })
-                // .on_request(|_request: &axum::http::Request<_>, _span: &tracing::Span| { info!("started processing request"); })
-                .on_response(
-                    |response: &axum::http::Response<_>,
-                     latency: std::time::Duration,
-                     _span: &tracing::Span| {
-                        tracing::info!(
-                            status = %response.status(),
-                            latency = ?latency,
-                        );
-                    },
-                ),
-        );
+    let app = router(state).layer(cors).layer(
+        TraceLayer::new_for_http()
+            .make_span_with(|request: &axum::http::Request<_>| {
+                tracing::info_span!(
+                    "http_request",
+                    method = %request.method(),
+                    uri = %request.uri(),
+                )
+            })
+            // .on_request(|_request: &axum::http::Request<_>, _span: &tracing::Span| { info!("started processing request"); })
+            .on_response(
+                |response: &axum::http::Response<_>,
+                 latency: std::time::Duration,
+                 _span: &tracing::Span| {
+                    tracing::info!(
+                        status = %response.status(),
+                        latency = ?latency,
+                    );
+                },
+            ),
+    );
 
     // 启动服务器
     let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
@@ -61,3 +90,38 @@ async fn main() {
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
+
+// 所有接口的路由，不含 CORS 与日志中间件
+fn router(state: Arc<state::AppState>) -> Router {
+    Router::new()
+        .route(
+            "/v1/chat/completions",
+            post(handlers::chat::chat_completions),
+        )
+        .route(
+            "/v1/completions",
+            post(handlers::text_completion::completions),
+        )
+        .route("/v1/messages", post(handlers::anthropic::messages))
+        .route(
+            "/v1beta/models/:model",
+            post(handlers::gemini::generate_content),
+        )
+        .route("/v1/responses", post(handlers::responses::create_response))
+        .route("/api/chat", post(handlers::ollama::chat))
+        .route("/api/generate", post(handlers::ollama::generate))
+        .route("/api/tags", get(handlers::ollama::tags))
+        .route(
+            "/admin/tokens",
+            get(handlers::admin::list_tokens).post(handlers::admin::add_tokens),
+        )
+        .route("/admin/status", get(handlers::admin::status))
+        .route("/admin/tokens/quota", get(handlers::admin::token_quota))
+        .route(
+            "/admin/tokens/:id",
+            patch(handlers::admin::update_token).delete(handlers::admin::delete_token),
+        )
+        .route("/models", get(handlers::models::models))
+        .route("/v1/models", get(handlers::models::models))
+        .with_state(state)
+}
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+
+// Anthropic Messages 接口的请求，temperature 等上游不支持的字段会被忽略
+#[derive(Debug, Deserialize)]
+pub struct MessagesRequest {
+    pub model: String,
+    pub max_tokens: u64,
+    pub messages: Vec<Message>,
+    #[serde(default)]
+    pub system: Option<Content>,
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    #[serde(default)]
+    pub stream: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: Content,
+}
+
+// 内容可以是字符串，也可以是内容块数组
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: ImageSource,
+    },
+    ToolUse {
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        #[serde(default)]
+        content: Option<Content>,
+    },
+    // thinking、document 等其余内容块不发往上游
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    Base64 { media_type: String },
+    Url { url: String },
+}
+
+impl std::fmt::Display for Content {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Content::Text(text) => write!(f, "{}", text),
+            Content::Blocks(blocks) => {
+                let parts: Vec<String> = blocks
+                    .iter()
+                    .filter(|block| !matches!(block, ContentBlock::Other))
+                    .map(ContentBlock::to_string)
+                    .collect();
+                write!(f, "{}", parts.join(", "))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ContentBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContentBlock::Text { text } => write!(f, "{}", text),
+            // base64 图片不放进提示词
+            ContentBlock::Image {
+                source: ImageSource::Base64 { media_type },
+            } => write!(f, "[Image: {}]", media_type),
+            ContentBlock::Image {
+                source: ImageSource::Url { url },
+            } => write!(f, "[Image: {}]", url),
+            ContentBlock::ToolUse { name, input } => write!(f, "[Tool call {}: {}]", name, input),
+            ContentBlock::ToolResult { content } => match content {
+                Some(content) => write!(f, "[Tool result: {}]", content),
+                None => write!(f, "[Tool result]"),
+            },
+            ContentBlock::Other => Ok(()),
+        }
+    }
+}
+
+// 非流式响应
+#[derive(Debug, Serialize)]
+pub struct MessagesResponse {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub object: String,
+    pub role: String,
+    pub model: String,
+    pub content: Vec<ResponseBlock>,
+    pub stop_reason: String,
+    pub stop_sequence: Option<String>,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseBlock {
+    Text { text: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_request_deserialization() {
+        let request: MessagesRequest = serde_json::from_str(
+            r#"{
+                "model": "claude-3.5-sonnet",
+                "max_tokens": 1024,
+                "system": [{"type": "text", "text": "Be brief."}],
+                "stop_sequences": ["\n\nHuman:"],
+                "temperature": 0.2,
+                "messages": [
+                    {"role": "user", "content": "Hello"},
+                    {"role": "assistant", "content": [
+                        {"type": "thinking", "thinking": "..."},
+                        {"type": "tool_use", "id": "toolu_01", "name": "search", "input": {"q": "rust"}}
+                    ]},
+                    {"role": "user", "content": [
+                        {"type": "tool_result", "tool_use_id": "toolu_01", "content": "found"},
+                        {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}},
+                        {"type": "text", "text": "What is this?"}
+                    ]}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(!request.stream);
+        assert_eq!(request.system.unwrap().to_string(), "Be brief.");
+        assert_eq!(
+            request.messages[1].content.to_string(),
+            r#"[Tool call search: {"q":"rust"}]"#
+        );
+        assert_eq!(
+            request.messages[2].content.to_string(),
+            "[Tool result: found], [Image: image/png], What is this?"
+        );
+
+        // max_tokens 为必填字段
+        assert!(serde_json::from_str::<MessagesRequest>(
+            r#"{"model": "claude-3.5-sonnet", "messages": []}"#
+        )
+        .is_err());
+    }
+}
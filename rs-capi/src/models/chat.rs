@@ -10,7 +10,7 @@ pub struct Message {
 }
 
 // 添加一个辅助枚举
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum SingleOrVec<T> {
     Single(T),
@@ -105,9 +105,9 @@ pub struct ResponseMessage {
 
 #[derive(Debug, Serialize)]
 pub struct Usage {
-    pub prompt_tokens: i32,
-    pub completion_tokens: i32,
-    pub total_tokens: i32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -0,0 +1,391 @@
+use crate::proto::error::ConnectError;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+// OpenAI 格式的错误响应
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorDetail {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub error_type: String,
+    pub param: Option<String>,
+    pub code: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(message: impl Into<String>, error_type: &str, code: Option<&str>) -> Self {
+        Self {
+            error: ErrorDetail {
+                message: message.into(),
+                error_type: error_type.to_string(),
+                param: None,
+                code: code.map(str::to_string),
+            },
+        }
+    }
+
+    pub fn with_param(mut self, param: &str) -> Self {
+        self.error.param = Some(param.to_string());
+        self
+    }
+
+    // 将上游 Connect 错误映射为 HTTP 状态码与 OpenAI 错误
+    pub fn from_upstream(error: &ConnectError) -> (StatusCode, Self) {
+        // 上游 token 失效是代理一侧的问题，不返回 401，以免客户端误以为自己的 key 无效
+        let (status, error_type, code) = if error.is_auth_error() {
+            (StatusCode::BAD_GATEWAY, "api_error", "upstream_auth_failed")
+        } else if error.is_usage_exhausted() {
+            (
+                StatusCode::PAYMENT_REQUIRED,
+                "insufficient_quota",
+                "insufficient_quota",
+            )
+        } else if error.is_rate_limited() {
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limit_error",
+                "rate_limit_exceeded",
+            )
+        } else if error.debug_error.as_deref() == Some("ERROR_BAD_MODEL_NAME") {
+            (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "model_not_found",
+            )
+        } else {
+            match error.code.as_str() {
+                "invalid_argument" | "not_found" | "failed_precondition" | "out_of_range" => (
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    "invalid_request",
+                ),
+                "permission_denied" => (
+                    StatusCode::FORBIDDEN,
+                    "permission_error",
+                    "permission_denied",
+                ),
+                _ => (StatusCode::BAD_GATEWAY, "api_error", "upstream_error"),
+            }
+        };
+
+        (
+            status,
+            Self::new(error.display_message(), error_type, Some(code)),
+        )
+    }
+}
+
+// 请求处理过程中的错误，统一输出 OpenAI 格式的错误响应
+#[derive(Debug)]
+pub enum ApiError {
+    // 读取请求体失败
+    BodyRead(String),
+    // 请求体不是合法的 JSON 或字段不符合要求
+    InvalidJson(String),
+    // 缺少 Authorization 头或格式错误
+    MissingApiKey,
+    // API key 不存在
+    InvalidApiKey,
+    // API key 已过期
+    KeyExpired,
+    // API key 无权使用该模型
+    ModelNotAllowed(String),
+    // API key 当日请求数已用完
+    DailyQuotaExceeded(u32),
+    // API key 并发流式请求数已达上限
+    TooManyStreams(u32),
+    // 未配置 ADMIN_KEY
+    AdminDisabled,
+    // 管理接口密钥错误
+    InvalidAdminKey,
+    // 管理接口中指定的 token 不存在
+    TokenNotFound(String),
+    // 服务端没有配置任何上游 token
+    NoUpstreamToken,
+    // 请求参数不被支持
+    InvalidRequest { message: String, param: String },
+    // 无法连接上游或读取上游响应失败
+    UpstreamRequest(String),
+    // 上游返回非 2xx 的 HTTP 状态码
+    UpstreamStatus(u16),
+    // 上游在结束帧中返回的错误
+    Upstream(ConnectError),
+    // 上游响应无法解码
+    Decode(String),
+    // 所有候选 token 都处于冷却中、已过期或额度已用完
+    NoAvailableToken,
+    Internal(String),
+}
+
+impl ApiError {
+    // 与该 token 本身有关的错误（认证失效、额度用完、限流），换一个 token 可能成功
+    pub fn is_token_error(&self) -> bool {
+        match self {
+            ApiError::UpstreamStatus(status) => matches!(status, 401 | 403 | 429),
+            ApiError::Upstream(err) => err.is_token_error(),
+            _ => false,
+        }
+    }
+
+    pub fn status_and_body(&self) -> (StatusCode, ErrorResponse) {
+        match self {
+            ApiError::BodyRead(err) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(
+                    format!("读取请求体失败: {}", err),
+                    "invalid_request_error",
+                    None,
+                ),
+            ),
+            ApiError::InvalidJson(err) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(
+                    format!("无法解析请求 JSON: {}", err),
+                    "invalid_request_error",
+                    Some("invalid_json"),
+                ),
+            ),
+            ApiError::MissingApiKey => (
+                StatusCode::UNAUTHORIZED,
+                ErrorResponse::new(
+                    "缺少 API key，请通过 Authorization: Bearer <key> 提供",
+                    "invalid_request_error",
+                    Some("missing_api_key"),
+                ),
+            ),
+            ApiError::InvalidApiKey => (
+                StatusCode::UNAUTHORIZED,
+                ErrorResponse::new(
+                    "API key 无效",
+                    "invalid_request_error",
+                    Some("invalid_api_key"),
+                ),
+            ),
+            ApiError::KeyExpired => (
+                StatusCode::FORBIDDEN,
+                ErrorResponse::new(
+                    "API key 已过期",
+                    "permission_error",
+                    Some("api_key_expired"),
+                ),
+            ),
+            ApiError::ModelNotAllowed(model) => (
+                StatusCode::FORBIDDEN,
+                ErrorResponse::new(
+                    format!("API key 无权使用模型 {}", model),
+                    "permission_error",
+                    Some("model_not_allowed"),
+                )
+                .with_param("model"),
+            ),
+            ApiError::DailyQuotaExceeded(limit) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorResponse::new(
+                    format!("已达到每日请求上限 {}", limit),
+                    "rate_limit_error",
+                    Some("daily_quota_exceeded"),
+                ),
+            ),
+            ApiError::TooManyStreams(limit) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorResponse::new(
+                    format!("并发流式请求数已达上限 {}", limit),
+                    "rate_limit_error",
+                    Some("concurrent_stream_limit"),
+                ),
+            ),
+            ApiError::AdminDisabled => (
+                StatusCode::FORBIDDEN,
+                ErrorResponse::new(
+                    "管理接口未启用，请配置 ADMIN_KEY",
+                    "permission_error",
+                    Some("admin_disabled"),
+                ),
+            ),
+            ApiError::InvalidAdminKey => (
+                StatusCode::FORBIDDEN,
+                ErrorResponse::new(
+                    "管理接口密钥错误",
+                    "permission_error",
+                    Some("invalid_admin_key"),
+                ),
+            ),
+            ApiError::TokenNotFound(id) => (
+                StatusCode::NOT_FOUND,
+                ErrorResponse::new(
+                    format!("token {} 不存在", id),
+                    "invalid_request_error",
+                    Some("token_not_found"),
+                ),
+            ),
+            ApiError::NoUpstreamToken => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResponse::new(
+                    "服务端未配置 Cursor token",
+                    "server_error",
+                    Some("no_upstream_token"),
+                ),
+            ),
+            ApiError::InvalidRequest { message, param } => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(message.as_str(), "invalid_request_error", None)
+                    .with_param(param),
+            ),
+            ApiError::UpstreamRequest(err) => (
+                StatusCode::BAD_GATEWAY,
+                ErrorResponse::new(
+                    format!("上游请求失败: {}", err),
+                    "api_error",
+                    Some("upstream_error"),
+                ),
+            ),
+            ApiError::UpstreamStatus(status @ (401 | 403)) => (
+                StatusCode::BAD_GATEWAY,
+                ErrorResponse::new(
+                    format!("上游 token 认证失败: HTTP {}", status),
+                    "api_error",
+                    Some("upstream_auth_failed"),
+                ),
+            ),
+            ApiError::UpstreamStatus(429) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorResponse::new(
+                    "上游请求过于频繁: HTTP 429",
+                    "rate_limit_error",
+                    Some("rate_limit_exceeded"),
+                ),
+            ),
+            ApiError::UpstreamStatus(status) => (
+                StatusCode::BAD_GATEWAY,
+                ErrorResponse::new(
+                    format!("上游请求失败: HTTP {}", status),
+                    "api_error",
+                    Some("upstream_error"),
+                ),
+            ),
+            ApiError::Upstream(err) => ErrorResponse::from_upstream(err),
+            ApiError::Decode(err) => (
+                StatusCode::BAD_GATEWAY,
+                ErrorResponse::new(
+                    format!("上游响应解码失败: {}", err),
+                    "api_error",
+                    Some("upstream_decode_error"),
+                ),
+            ),
+            ApiError::NoAvailableToken => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorResponse::new(
+                    "没有可用的 token（均处于冷却中、已过期或额度已用完），请稍后重试",
+                    "rate_limit_error",
+                    Some("no_available_token"),
+                ),
+            ),
+            ApiError::Internal(err) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(err.as_str(), "server_error", None),
+            ),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, body) = self.status_and_body();
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect_error(code: &str, debug_error: Option<&str>) -> ConnectError {
+        ConnectError {
+            code: code.to_string(),
+            message: "upstream message".to_string(),
+            debug_error: debug_error.map(str::to_string),
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn test_upstream_error_status() {
+        let cases = [
+            ("unauthenticated", None, StatusCode::BAD_GATEWAY),
+            (
+                "permission_denied",
+                Some("ERROR_NOT_LOGGED_IN"),
+                StatusCode::BAD_GATEWAY,
+            ),
+            (
+                "resource_exhausted",
+                Some("ERROR_PRO_USER_USAGE_LIMIT"),
+                StatusCode::PAYMENT_REQUIRED,
+            ),
+            (
+                "resource_exhausted",
+                Some("ERROR_FREE_USER_RATE_LIMIT_EXCEEDED"),
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+            ("resource_exhausted", None, StatusCode::TOO_MANY_REQUESTS),
+            (
+                "invalid_argument",
+                Some("ERROR_BAD_MODEL_NAME"),
+                StatusCode::BAD_REQUEST,
+            ),
+            ("internal", None, StatusCode::BAD_GATEWAY),
+        ];
+        for (code, debug, expected) in cases {
+            let (status, _) = ErrorResponse::from_upstream(&connect_error(code, debug));
+            assert_eq!(status, expected, "code = {}, debug = {:?}", code, debug);
+        }
+    }
+
+    #[test]
+    fn test_api_error_status() {
+        assert_eq!(
+            ApiError::InvalidJson("expected value".to_string())
+                .status_and_body()
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            ApiError::MissingApiKey.status_and_body().0,
+            StatusCode::UNAUTHORIZED
+        );
+        let (status, body) = ApiError::InvalidRequest {
+            message: "o1 模型不支持流式输出".to_string(),
+            param: "stream".to_string(),
+        }
+        .status_and_body();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.error.param.as_deref(), Some("stream"));
+
+        let (status, body) = ApiError::UpstreamStatus(401).status_and_body();
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body.error.code.as_deref(), Some("upstream_auth_failed"));
+    }
+
+    #[test]
+    fn test_error_response_serialization() {
+        let (_, body) = ErrorResponse::from_upstream(&connect_error("unauthenticated", None));
+        assert_eq!(
+            serde_json::to_value(body).unwrap(),
+            serde_json::json!({
+                "error": {
+                    "message": "upstream message",
+                    "type": "api_error",
+                    "param": null,
+                    "code": "upstream_auth_failed"
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use serde::Deserialize;
+
+// Gemini generateContent 接口的请求，字段名兼容驼峰与下划线两种写法
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentRequest {
+    pub contents: Vec<Content>,
+    #[serde(default, alias = "system_instruction")]
+    pub system_instruction: Option<Content>,
+    #[serde(default, alias = "generation_config")]
+    pub generation_config: GenerationConfig,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Content {
+    // user 或 model，多轮对话以外可以省略
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<Part>,
+}
+
+// 每个 part 只有一个字段有值
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Part {
+    pub text: Option<String>,
+    #[serde(alias = "inline_data")]
+    pub inline_data: Option<Blob>,
+    #[serde(alias = "file_data")]
+    pub file_data: Option<FileData>,
+    #[serde(alias = "function_call")]
+    pub function_call: Option<FunctionCall>,
+    #[serde(alias = "function_response")]
+    pub function_response: Option<FunctionResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Blob {
+    #[serde(alias = "mime_type")]
+    pub mime_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileData {
+    #[serde(alias = "file_uri")]
+    pub file_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub args: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FunctionResponse {
+    pub name: String,
+    #[serde(default)]
+    pub response: serde_json::Value,
+}
+
+// temperature 等上游不支持的字段会被忽略
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    #[serde(default, alias = "stop_sequences")]
+    pub stop_sequences: Vec<String>,
+    #[serde(alias = "max_output_tokens")]
+    pub max_output_tokens: Option<u64>,
+}
+
+impl Content {
+    // 转换为 chat/completions 使用的角色名
+    pub fn role(&self) -> &str {
+        match self.role.as_deref() {
+            Some("model") => "assistant",
+            Some(role) => role,
+            None => "user",
+        }
+    }
+}
+
+impl std::fmt::Display for Content {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = self.parts.iter().map(Part::to_string).collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl std::fmt::Display for Part {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(text) = &self.text {
+            write!(f, "{}", text)
+        } else if let Some(blob) = &self.inline_data {
+            // 内联数据不放进提示词
+            if blob.mime_type.starts_with("image/") {
+                write!(f, "[Image: {}]", blob.mime_type)
+            } else {
+                write!(f, "[File: {}]", blob.mime_type)
+            }
+        } else if let Some(file) = &self.file_data {
+            write!(f, "[File: {}]", file.file_uri)
+        } else if let Some(call) = &self.function_call {
+            write!(f, "[Tool call {}: {}]", call.name, call.args)
+        } else if let Some(response) = &self.function_response {
+            write!(f, "[Tool result {}: {}]", response.name, response.response)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_content_request_deserialization() {
+        let request: GenerateContentRequest = serde_json::from_str(
+            r#"{
+                "system_instruction": {"parts": [{"text": "Be brief."}]},
+                "contents": [
+                    {"role": "user", "parts": [
+                        {"text": "What is this?"},
+                        {"inlineData": {"mimeType": "image/jpeg", "data": "/9j/4AAQ"}}
+                    ]},
+                    {"role": "model", "parts": [{"functionCall": {"name": "search", "args": {"q": "cat"}}}]},
+                    {"parts": [{"text": "Thanks"}]}
+                ],
+                "generationConfig": {"stopSequences": ["END"], "maxOutputTokens": 256, "temperature": 0.5}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(request.system_instruction.unwrap().to_string(), "Be brief.");
+        assert_eq!(
+            request.contents[0].to_string(),
+            "What is this?, [Image: image/jpeg]"
+        );
+        assert_eq!(request.contents[1].role(), "assistant");
+        assert_eq!(
+            request.contents[1].to_string(),
+            r#"[Tool call search: {"q":"cat"}]"#
+        );
+        assert_eq!(request.contents[2].role(), "user");
+        assert_eq!(request.generation_config.stop_sequences, vec!["END"]);
+        assert_eq!(request.generation_config.max_output_tokens, Some(256));
+    }
+}
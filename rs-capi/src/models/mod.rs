@@ -1 +1,7 @@
+pub mod anthropic;
 pub mod chat;
+pub mod error;
+pub mod gemini;
+pub mod ollama;
+pub mod responses;
+pub mod text_completion;
@@ -0,0 +1,100 @@
+use serde::Deserialize;
+
+// Ollama 的 stream 默认为 true
+fn default_stream() -> bool {
+    true
+}
+
+// /api/chat 的请求，format、tools、keep_alive 等字段会被忽略
+#[derive(Debug, Deserialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    #[serde(default)]
+    pub options: Options,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    // base64 编码的图片
+    #[serde(default)]
+    pub images: Vec<String>,
+}
+
+// /api/generate 的请求，template、context、raw 等字段会被忽略
+#[derive(Debug, Deserialize)]
+pub struct GenerateRequest {
+    pub model: String,
+    #[serde(default)]
+    pub prompt: String,
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub images: Vec<String>,
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    #[serde(default)]
+    pub options: Options,
+}
+
+// 模型参数中上游能支持的部分，temperature 等其余参数会被忽略
+#[derive(Debug, Default, Deserialize)]
+pub struct Options {
+    #[serde(default)]
+    pub stop: Vec<String>,
+    // 负数表示不限
+    pub num_predict: Option<i64>,
+}
+
+impl Options {
+    pub fn max_tokens(&self) -> Option<u64> {
+        self.num_predict
+            .and_then(|num_predict| u64::try_from(num_predict).ok())
+    }
+}
+
+// 文本后附上图片占位，图片本身不放进提示词
+pub fn with_images(content: &str, images: &[String]) -> String {
+    std::iter::once(content.to_string())
+        .filter(|content| !content.is_empty())
+        .chain(images.iter().map(|_| "[Image]".to_string()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ollama_request_deserialization() {
+        let request: ChatRequest = serde_json::from_str(
+            r#"{
+                "model": "gpt-4o:latest",
+                "messages": [{"role": "user", "content": "What is this?", "images": ["iVBORw0KGgo="]}],
+                "options": {"stop": ["END"], "num_predict": 128, "temperature": 0.1},
+                "keep_alive": "5m"
+            }"#,
+        )
+        .unwrap();
+        assert!(request.stream);
+        assert_eq!(request.options.max_tokens(), Some(128));
+        let message = &request.messages[0];
+        assert_eq!(
+            with_images(&message.content, &message.images),
+            "What is this?, [Image]"
+        );
+
+        let request: GenerateRequest = serde_json::from_str(
+            r#"{"model": "gpt-4o", "prompt": "Hi", "stream": false, "options": {"num_predict": -1}}"#,
+        )
+        .unwrap();
+        assert!(!request.stream);
+        assert_eq!(request.options.max_tokens(), None);
+    }
+}
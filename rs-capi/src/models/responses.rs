@@ -0,0 +1,202 @@
+use crate::response_store::Turn;
+use serde::Deserialize;
+
+fn default_store() -> bool {
+    true
+}
+
+// OpenAI Responses 接口的请求，tools、reasoning、temperature 等字段会被忽略
+#[derive(Debug, Deserialize)]
+pub struct ResponsesRequest {
+    pub model: String,
+    pub input: Input,
+    // 不会随 previous_response_id 继承，每次请求单独指定
+    #[serde(default)]
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub previous_response_id: Option<String>,
+    #[serde(default)]
+    pub stream: bool,
+    pub max_output_tokens: Option<u64>,
+    // 为 false 时不保存本次对话，之后不能再用它的 id 续接
+    #[serde(default = "default_store")]
+    pub store: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Input {
+    Text(String),
+    Items(Vec<InputItem>),
+}
+
+// 输入项：消息（type 可省略）、function_call、function_call_output，其余类型（如 reasoning）会被忽略
+#[derive(Debug, Deserialize)]
+pub struct InputItem {
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub content: Option<MessageContent>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: Option<String>,
+    #[serde(default)]
+    pub output: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    InputText {
+        text: String,
+    },
+    // 多轮对话中回传的助手输出
+    OutputText {
+        text: String,
+    },
+    Refusal {
+        refusal: String,
+    },
+    InputImage {
+        #[serde(default)]
+        image_url: Option<String>,
+    },
+    InputFile {
+        #[serde(default)]
+        filename: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl Input {
+    // 转换为 role:content 形式的对话
+    pub fn turns(&self) -> Vec<Turn> {
+        match self {
+            Input::Text(text) => vec![Turn::new("user", text.as_str())],
+            Input::Items(items) => items.iter().filter_map(InputItem::turn).collect(),
+        }
+    }
+}
+
+impl InputItem {
+    fn turn(&self) -> Option<Turn> {
+        match self.kind.as_deref() {
+            None | Some("message") => {
+                let content = self
+                    .content
+                    .as_ref()
+                    .map(MessageContent::to_string)
+                    .unwrap_or_default();
+                Some(Turn::new(self.role.as_deref().unwrap_or("user"), content))
+            }
+            Some("function_call") => Some(Turn::new(
+                "assistant",
+                format!(
+                    "[Tool call {}: {}]",
+                    self.name.as_deref().unwrap_or_default(),
+                    self.arguments.as_deref().unwrap_or_default()
+                ),
+            )),
+            Some("function_call_output") => {
+                let output = match &self.output {
+                    Some(serde_json::Value::String(output)) => output.clone(),
+                    Some(output) => output.to_string(),
+                    None => String::new(),
+                };
+                Some(Turn::new("tool", format!("[Tool result: {}]", output)))
+            }
+            Some(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for MessageContent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageContent::Text(text) => write!(f, "{}", text),
+            MessageContent::Parts(parts) => {
+                let parts: Vec<String> = parts
+                    .iter()
+                    .map(ContentPart::to_string)
+                    .filter(|part| !part.is_empty())
+                    .collect();
+                write!(f, "{}", parts.join(", "))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ContentPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContentPart::InputText { text } | ContentPart::OutputText { text } => {
+                write!(f, "{}", text)
+            }
+            ContentPart::Refusal { refusal } => write!(f, "{}", refusal),
+            // data URL 不放进提示词
+            ContentPart::InputImage {
+                image_url: Some(url),
+            } if !url.starts_with("data:") => write!(f, "[Image: {}]", url),
+            ContentPart::InputImage { .. } => write!(f, "[Image]"),
+            ContentPart::InputFile {
+                filename: Some(filename),
+            } => write!(f, "[File: {}]", filename),
+            ContentPart::InputFile { filename: None } => write!(f, "[File]"),
+            ContentPart::Other => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_responses_request_deserialization() {
+        let request: ResponsesRequest =
+            serde_json::from_str(r#"{"model": "gpt-4o", "input": "Hi"}"#).unwrap();
+        assert!(request.store);
+        assert_eq!(request.input.turns(), vec![Turn::new("user", "Hi")]);
+
+        let request: ResponsesRequest = serde_json::from_str(
+            r#"{
+                "model": "gpt-4o",
+                "instructions": "Be brief.",
+                "input": [
+                    {"role": "user", "content": [
+                        {"type": "input_text", "text": "What is this?"},
+                        {"type": "input_image", "image_url": "data:image/png;base64,iVBORw0KGgo="}
+                    ]},
+                    {"type": "message", "role": "assistant", "content": [{"type": "output_text", "text": "A cat."}]},
+                    {"type": "reasoning", "summary": []},
+                    {"type": "function_call", "call_id": "call_1", "name": "search", "arguments": "{\"q\":\"cat\"}"},
+                    {"type": "function_call_output", "call_id": "call_1", "output": "found"}
+                ],
+                "store": false,
+                "max_output_tokens": 64
+            }"#,
+        )
+        .unwrap();
+        assert!(!request.store);
+        assert_eq!(
+            request.input.turns(),
+            vec![
+                Turn::new("user", "What is this?, [Image]"),
+                Turn::new("assistant", "A cat."),
+                Turn::new("assistant", r#"[Tool call search: {"q":"cat"}]"#),
+                Turn::new("tool", "[Tool result: found]"),
+            ]
+        );
+    }
+}
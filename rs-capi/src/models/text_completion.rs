@@ -0,0 +1,102 @@
+use crate::models::chat::SingleOrVec;
+use crate::models::error::ApiError;
+use serde::{Deserialize, Serialize};
+
+// 旧版 /v1/completions 的请求，n、logprobs、temperature 等字段会被忽略
+#[derive(Debug, Deserialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    // 字符串或只含一个字符串的数组，不支持 token id 数组
+    pub prompt: SingleOrVec<String>,
+    // 补全之后的文本，补全结果不包含它
+    #[serde(default)]
+    pub suffix: Option<String>,
+    pub max_tokens: Option<u64>,
+    #[serde(default)]
+    pub stop: Option<SingleOrVec<String>>,
+    #[serde(default)]
+    pub stream: bool,
+    // 为 true 时在补全结果前附上 prompt
+    #[serde(default)]
+    pub echo: bool,
+}
+
+impl CompletionRequest {
+    // 上游每次只生成一个回复，不支持一次请求多个 prompt
+    pub fn prompt(&self) -> Result<String, ApiError> {
+        match &self.prompt {
+            SingleOrVec::Single(prompt) => Ok(prompt.clone()),
+            SingleOrVec::Vec(prompts) if prompts.len() == 1 => Ok(prompts[0].clone()),
+            SingleOrVec::Vec(_) => Err(ApiError::InvalidRequest {
+                message: "prompt 数组只能包含一个字符串".to_string(),
+                param: "prompt".to_string(),
+            }),
+        }
+    }
+
+    pub fn stop(&self) -> Vec<String> {
+        match &self.stop {
+            Some(SingleOrVec::Single(stop)) => vec![stop.clone()],
+            Some(SingleOrVec::Vec(stop)) => stop.clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+// text_completion 对象，流式响应中每段文本各一个，只有最后一个带 finish_reason
+#[derive(Debug, Serialize)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<CompletionChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompletionChoice {
+    pub text: String,
+    pub index: i32,
+    // 不支持 logprobs，始终为 null
+    pub logprobs: Option<serde_json::Value>,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_request_deserialization() {
+        let request: CompletionRequest = serde_json::from_str(
+            r#"{"model": "gpt-3.5-turbo", "prompt": ["Once"], "stop": "\n\n", "echo": true, "n": 1}"#,
+        )
+        .unwrap();
+        assert_eq!(request.prompt().unwrap(), "Once");
+        assert_eq!(request.stop(), vec!["\n\n"]);
+        assert!(request.echo);
+
+        let request: CompletionRequest =
+            serde_json::from_str(r#"{"model": "gpt-3.5-turbo", "prompt": ["Once", "upon"]}"#)
+                .unwrap();
+        assert!(matches!(
+            request.prompt(),
+            Err(ApiError::InvalidRequest { param, .. }) if param == "prompt"
+        ));
+
+        // token id 数组不支持
+        assert!(serde_json::from_str::<CompletionRequest>(
+            r#"{"model": "gpt-3.5-turbo", "prompt": [1, 2, 3]}"#
+        )
+        .is_err());
+    }
+}
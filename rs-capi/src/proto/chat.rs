@@ -0,0 +1,370 @@
+use super::error::ConnectError;
+use super::{encode_envelope, DecodeError, FieldValue, FrameReader, ProtoReader, ProtoWriter};
+use uuid::Uuid;
+
+// 默认工作区路径，沿用原请求模板中的值
+pub const DEFAULT_WORKSPACE_PATH: &str = "/d:/ideaPro/eduboss";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Human = 1,
+    #[allow(dead_code)]
+    Assistant = 2,
+}
+
+// aiserver.v1.ConversationMessage
+#[derive(Debug, Clone)]
+pub struct ConversationMessage {
+    pub text: String,
+    pub message_type: MessageType,
+    pub bubble_id: String,
+}
+
+impl ConversationMessage {
+    pub fn new(text: impl Into<String>, message_type: MessageType) -> Self {
+        Self {
+            text: text.into(),
+            message_type,
+            bubble_id: Uuid::new_v4().to_string(),
+        }
+    }
+
+    fn encode(&self) -> ProtoWriter {
+        let mut w = ProtoWriter::new();
+        w.string(1, &self.text)
+            .uint64(2, self.message_type as u64)
+            .string(13, &self.bubble_id);
+        w
+    }
+}
+
+// aiserver.v1.ModelDetails
+#[derive(Debug, Clone)]
+pub struct ModelDetails {
+    pub model_name: String,
+}
+
+impl ModelDetails {
+    fn encode(&self) -> ProtoWriter {
+        let mut w = ProtoWriter::new();
+        w.string(1, &self.model_name)
+            // azure_state
+            .message(4, &ProtoWriter::new());
+        w
+    }
+}
+
+// aiserver.v1.GetChatRequest，StreamChat 接口的请求体
+#[derive(Debug, Clone)]
+pub struct GetChatRequest {
+    pub conversation: Vec<ConversationMessage>,
+    pub workspace_root_path: String,
+    pub model_details: ModelDetails,
+    pub request_id: String,
+    pub conversation_id: String,
+    pub allow_long_file_scan: bool,
+    pub is_bash: bool,
+    pub can_handle_filenames_after_language_ids: bool,
+    pub long_context_mode: bool,
+    pub is_eval: bool,
+    pub is_composer: bool,
+    pub runnable_code_blocks: bool,
+}
+
+impl GetChatRequest {
+    pub fn new(conversation: Vec<ConversationMessage>, model_name: &str) -> Self {
+        Self {
+            conversation,
+            workspace_root_path: DEFAULT_WORKSPACE_PATH.to_string(),
+            model_details: ModelDetails {
+                model_name: model_name.to_string(),
+            },
+            request_id: Uuid::new_v4().to_string(),
+            conversation_id: Uuid::new_v4().to_string(),
+            allow_long_file_scan: false,
+            is_bash: false,
+            can_handle_filenames_after_language_ids: true,
+            long_context_mode: false,
+            is_eval: false,
+            is_composer: false,
+            runnable_code_blocks: false,
+        }
+    }
+
+    // 编码为 protobuf 消息，布尔字段即使为 false 也显式写出，与官方客户端保持一致
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = ProtoWriter::new();
+        for message in &self.conversation {
+            w.message(2, &message.encode());
+        }
+        w.message(4, &ProtoWriter::new())
+            .string(5, &self.workspace_root_path)
+            .message(7, &self.model_details.encode())
+            .string(9, &self.request_id)
+            .bool(13, self.allow_long_file_scan)
+            .bool(14, self.is_bash)
+            .string(15, &self.conversation_id)
+            .bool(16, self.can_handle_filenames_after_language_ids)
+            .bool(22, self.long_context_mode)
+            .bool(24, self.is_eval)
+            .bool(28, self.is_composer)
+            .bool(29, self.runnable_code_blocks);
+        w.into_bytes()
+    }
+
+    // 编码并封装为未压缩的 Connect 帧
+    pub fn encode_frame(&self) -> Vec<u8> {
+        encode_envelope(0, &self.encode())
+    }
+}
+
+// aiserver.v1.StreamChatResponse，只关心文本增量
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StreamChatResponse {
+    pub text: String,
+}
+
+impl StreamChatResponse {
+    pub fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+        let mut response = Self::default();
+        for field in ProtoReader::new(buf) {
+            if let (1, FieldValue::Bytes(text)) = field? {
+                response.text.push_str(&String::from_utf8_lossy(text));
+            }
+        }
+        Ok(response)
+    }
+}
+
+// 上游响应流中的一条消息
+#[derive(Debug, PartialEq, Eq)]
+pub enum StreamMessage {
+    Text(String),
+    Error(ConnectError),
+    End,
+}
+
+// 取出下一条已完整到达的消息，数据不足时返回 None
+pub fn next_message(reader: &mut FrameReader) -> Result<Option<StreamMessage>, DecodeError> {
+    let Some(envelope) = reader.next_frame()? else {
+        return Ok(None);
+    };
+    if envelope.is_end_stream() {
+        return Ok(Some(
+            match ConnectError::from_end_stream(&envelope.payload) {
+                Some(error) => StreamMessage::Error(error),
+                None => StreamMessage::End,
+            },
+        ));
+    }
+    let response = StreamChatResponse::decode(&envelope.payload)?;
+    Ok(Some(StreamMessage::Text(response.text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 原 hex_utils::string_to_hex 的十六进制模板，仅用于兼容性对比
+    fn legacy_string_to_hex(text: &str, model_name: &str) -> Vec<u8> {
+        let text_bytes = text.as_bytes();
+        let text_length = text_bytes.len();
+
+        const FIXED_HEADER: usize = 2;
+        const SEPARATOR: usize = 1;
+
+        let model_name_bytes = model_name.as_bytes();
+        let fixed_suffix_length = 0xA3 + model_name_bytes.len();
+
+        let (text_length_field1, text_length_field_size1) = if text_length < 128 {
+            (format!("{:02x}", text_length), 1)
+        } else {
+            let low_byte1 = (text_length & 0x7F) | 0x80;
+            let high_byte1 = (text_length >> 7) & 0xFF;
+            (format!("{:02x}{:02x}", low_byte1, high_byte1), 2)
+        };
+
+        let base_length = text_length + 0x2A;
+        let (text_length_field, text_length_field_size) = if base_length < 128 {
+            (format!("{:02x}", base_length), 1)
+        } else {
+            let low_byte = (base_length & 0x7F) | 0x80;
+            let high_byte = (base_length >> 7) & 0xFF;
+            (format!("{:02x}{:02x}", low_byte, high_byte), 2)
+        };
+
+        let message_total_length = FIXED_HEADER
+            + text_length_field_size
+            + SEPARATOR
+            + text_length_field_size1
+            + text_length
+            + fixed_suffix_length;
+
+        let model_name_length_hex = format!("{:02X}", model_name_bytes.len());
+        let hex_string = format!(
+            "{:010x}\
+            12{}\
+            0A{}\
+            {}\
+            10016A2432343163636435662D393162612D343131382D393239612D3936626330313631626432612\
+            2002A132F643A2F6964656150726F2F656475626F73733A1E0A\
+            {}{}\
+            22004A\
+            2461383761396133342D323164642D343863372D623434662D616636633365636536663765\
+            680070007A2436393337376535612D386332642D343835342D623564392D653062623232336163303061\
+            800101B00100C00100E00100E80100",
+            message_total_length,
+            text_length_field,
+            text_length_field1,
+            hex::encode_upper(text_bytes),
+            model_name_length_hex,
+            hex::encode_upper(model_name_bytes)
+        )
+        .to_uppercase();
+
+        hex::decode(hex_string).unwrap_or_default()
+    }
+
+    // 使用模板中固定的 id 构造请求
+    fn template_request(text: &str, model_name: &str) -> GetChatRequest {
+        let mut message = ConversationMessage::new(text, MessageType::Human);
+        message.bubble_id = "241ccd5f-91ba-4118-929a-96bc0161bd2a".to_string();
+        let mut request = GetChatRequest::new(vec![message], model_name);
+        request.request_id = "a87a9a34-21dd-48c7-b44f-af6c3ece6f7e".to_string();
+        request.conversation_id = "69377e5a-8c2d-4854-b5d9-e0bb223ac00a".to_string();
+        request
+    }
+
+    #[test]
+    fn test_matches_legacy_template() {
+        // 旧模板中 ModelDetails 的长度固定为 0x1E，只有 26 字节的模型名才能得到合法编码；
+        // 文本长度达到 128 字节后旧模板的外层长度也会算错，因此只比较短文本
+        let model = "claude-3-5-sonnet-20241022";
+        for text in [
+            "hi",
+            "user:你是谁",
+            &"a".repeat(85),
+            &"b".repeat(86),
+            &"c".repeat(127),
+        ] {
+            assert_eq!(
+                template_request(text, model).encode_frame(),
+                legacy_string_to_hex(text, model),
+                "text length = {}",
+                text.len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_prompt_length_fields() {
+        // 超过 16KB 时长度字段需要三字节 varint
+        let text = "x".repeat(20_000);
+        let frame = template_request(&text, "gpt-4o").encode_frame();
+
+        let payload_len = u32::from_be_bytes(frame[1..5].try_into().unwrap()) as usize;
+        assert_eq!(payload_len, frame.len() - 5);
+
+        // 0x12 <varint: 消息长度> 0x0A <varint: 20000>
+        let message_len = 1 + 3 + text.len() + 2 + 2 + 36;
+        let mut expected = vec![0x12];
+        crate::proto::encode_varint(message_len as u64, &mut expected);
+        expected.extend_from_slice(&[0x0A, 0xA0, 0x9C, 0x01]);
+        assert_eq!(&frame[5..5 + expected.len()], expected.as_slice());
+    }
+
+    fn response_frame(text: &str) -> Vec<u8> {
+        let mut w = ProtoWriter::new();
+        w.string(1, text);
+        encode_envelope(0, &w.into_bytes())
+    }
+
+    fn read_text(reader: &mut FrameReader) -> String {
+        let mut text = String::new();
+        while let Some(message) = next_message(reader).unwrap() {
+            if let StreamMessage::Text(delta) = message {
+                text.push_str(&delta);
+            }
+        }
+        text
+    }
+
+    fn decode_all(buf: &[u8]) -> String {
+        let mut reader = FrameReader::new();
+        reader.push(buf);
+        read_text(&mut reader)
+    }
+
+    #[test]
+    fn test_decode_preserves_whitespace() {
+        let text = "\n```rust\nfn main() {\n\tprintln!(\"你好\");\n}\n```\n\n";
+        assert_eq!(decode_all(&response_frame(text)), text);
+    }
+
+    #[test]
+    fn test_decode_multiple_frames() {
+        let mut buf = response_frame("Hello");
+        buf.extend_from_slice(&response_frame(", \n"));
+        buf.extend_from_slice(&encode_envelope(crate::proto::FLAG_END_STREAM, b"{}"));
+        assert_eq!(decode_all(&buf), "Hello, \n");
+    }
+
+    #[test]
+    fn test_decode_split_multibyte_text() {
+        // 在任意位置拆分都不能产生 U+FFFD
+        let mut stream = response_frame("你好，");
+        stream.extend_from_slice(&response_frame("世界🌏"));
+
+        for split in 0..=stream.len() {
+            let mut reader = FrameReader::new();
+            let mut text = String::new();
+            for part in [&stream[..split], &stream[split..]] {
+                reader.push(part);
+                text.push_str(&read_text(&mut reader));
+            }
+            assert_eq!(text, "你好，世界🌏", "split = {}", split);
+        }
+    }
+
+    #[test]
+    fn test_end_stream_messages() {
+        let mut reader = FrameReader::new();
+        reader.push(&response_frame("partial"));
+        reader.push(&encode_envelope(
+            crate::proto::FLAG_END_STREAM,
+            br#"{"error":{"code":"unauthenticated","message":"Not logged in"}}"#,
+        ));
+        assert_eq!(
+            next_message(&mut reader).unwrap(),
+            Some(StreamMessage::Text("partial".to_string()))
+        );
+        match next_message(&mut reader).unwrap() {
+            Some(StreamMessage::Error(error)) => assert_eq!(error.code, "unauthenticated"),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert_eq!(next_message(&mut reader).unwrap(), None);
+
+        reader.push(&encode_envelope(crate::proto::FLAG_END_STREAM, b"{}"));
+        assert_eq!(next_message(&mut reader).unwrap(), Some(StreamMessage::End));
+    }
+
+    #[test]
+    fn test_decode_skips_unknown_fields() {
+        let mut w = ProtoWriter::new();
+        w.uint64(2, 7)
+            .string(1, "a")
+            .string(5, "ignored")
+            .string(1, "b");
+        assert_eq!(
+            StreamChatResponse::decode(&w.into_bytes()).unwrap().text,
+            "ab"
+        );
+    }
+
+    #[test]
+    fn test_model_details_length() {
+        let frame = template_request("hi", "gpt-4o").encode_frame();
+        let needle = [0x3A, 0x0A, 0x0A, 0x06];
+        assert!(frame.windows(needle.len()).any(|w| w == needle));
+    }
+}
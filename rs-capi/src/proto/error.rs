@@ -0,0 +1,148 @@
+use serde_json::Value;
+
+// Connect 结束帧中携带的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectError {
+    // Connect 错误码，如 unauthenticated、resource_exhausted
+    pub code: String,
+    pub message: String,
+    // Cursor 在 details[].debug.error 中给出的具体错误，如 ERROR_NOT_LOGGED_IN
+    pub debug_error: Option<String>,
+    // 面向用户的错误说明
+    pub detail: Option<String>,
+}
+
+impl ConnectError {
+    // 解析结束帧的 JSON 负载，没有 error 字段时返回 None
+    pub fn from_end_stream(payload: &[u8]) -> Option<Self> {
+        let value: Value = match serde_json::from_slice(payload) {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::warn!("结束帧 JSON 解析失败: {}", err);
+                return None;
+            }
+        };
+        let error = value.get("error")?;
+
+        let debug = error
+            .get("details")
+            .and_then(Value::as_array)
+            .and_then(|details| details.iter().find_map(|d| d.get("debug")));
+
+        Some(Self {
+            code: error
+                .get("code")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown")
+                .to_string(),
+            message: error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            debug_error: debug
+                .and_then(|d| d.get("error"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            detail: debug
+                .and_then(|d| d.pointer("/details/detail"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    // token 失效或未登录
+    pub fn is_auth_error(&self) -> bool {
+        self.code == "unauthenticated"
+            || matches!(
+                self.debug_error.as_deref(),
+                Some(
+                    "ERROR_NOT_LOGGED_IN"
+                        | "ERROR_AUTH_TOKEN_NOT_FOUND"
+                        | "ERROR_AUTH_TOKEN_EXPIRED"
+                        | "ERROR_BAD_API_KEY"
+                        | "ERROR_BAD_USER_API_KEY"
+                )
+            )
+    }
+
+    // 账号用量已耗尽
+    pub fn is_usage_exhausted(&self) -> bool {
+        self.debug_error
+            .as_deref()
+            .is_some_and(|debug| debug.contains("USAGE"))
+    }
+
+    // 触发频率限制
+    pub fn is_rate_limited(&self) -> bool {
+        self.code == "resource_exhausted"
+            || self
+                .debug_error
+                .as_deref()
+                .is_some_and(|debug| debug.contains("RATE_LIMIT"))
+    }
+
+    // 与 token 本身相关、换一个 token 可能成功的错误
+    pub fn is_token_error(&self) -> bool {
+        self.is_auth_error() || self.is_usage_exhausted() || self.is_rate_limited()
+    }
+
+    // 优先使用面向用户的说明
+    pub fn display_message(&self) -> &str {
+        match &self.detail {
+            Some(detail) if !detail.is_empty() => detail,
+            _ if !self.message.is_empty() => &self.message,
+            _ => &self.code,
+        }
+    }
+}
+
+impl std::fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.debug_error {
+            Some(debug) => write!(f, "{} ({}): {}", self.code, debug, self.display_message()),
+            None => write!(f, "{}: {}", self.code, self.display_message()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_end_stream_error() {
+        let payload = br#"{"error":{"code":"resource_exhausted","message":"Error","details":[{"type":"aiserver.v1.ErrorDetails","debug":{"error":"ERROR_FREE_USER_USAGE_LIMIT","details":{"title":"Usage limit","detail":"You've hit the usage limit"},"isExpected":true}}]}}"#;
+        let error = ConnectError::from_end_stream(payload).unwrap();
+        assert_eq!(error.code, "resource_exhausted");
+        assert_eq!(
+            error.debug_error.as_deref(),
+            Some("ERROR_FREE_USER_USAGE_LIMIT")
+        );
+        assert_eq!(error.display_message(), "You've hit the usage limit");
+    }
+
+    #[test]
+    fn test_token_error_classification() {
+        let error = |code: &str, debug: Option<&str>| ConnectError {
+            code: code.to_string(),
+            message: String::new(),
+            debug_error: debug.map(str::to_string),
+            detail: None,
+        };
+        assert!(error("unauthenticated", None).is_token_error());
+        assert!(error("permission_denied", Some("ERROR_AUTH_TOKEN_EXPIRED")).is_auth_error());
+        assert!(
+            error("resource_exhausted", Some("ERROR_PRO_USER_USAGE_LIMIT")).is_usage_exhausted()
+        );
+        assert!(error("resource_exhausted", None).is_token_error());
+        assert!(!error("invalid_argument", Some("ERROR_BAD_MODEL_NAME")).is_token_error());
+        assert!(!error("internal", None).is_token_error());
+    }
+
+    #[test]
+    fn test_parse_end_stream_without_error() {
+        assert_eq!(ConnectError::from_end_stream(b"{}"), None);
+        assert_eq!(ConnectError::from_end_stream(br#"{"metadata":{}}"#), None);
+    }
+}
@@ -0,0 +1,411 @@
+pub mod chat;
+pub mod error;
+
+use bytes::{Buf, BytesMut};
+use flate2::read::GzDecoder;
+use std::io::Read;
+
+// protobuf 线格式类型
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_FIXED64: u64 = 1;
+const WIRE_TYPE_LEN: u64 = 2;
+const WIRE_TYPE_FIXED32: u64 = 5;
+
+// Connect 协议帧头: 1 字节标志位 + 4 字节大端长度
+pub const ENVELOPE_HEADER_LEN: usize = 5;
+// 帧标志位
+pub const FLAG_COMPRESSED: u8 = 0x01;
+pub const FLAG_END_STREAM: u8 = 0x02;
+// 单帧负载（解压后）的大小上限，防止异常帧占用过多内存
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    Truncated,
+    InvalidVarint,
+    UnsupportedWireType(u64),
+    Decompress(String),
+    FrameTooLarge,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "数据不完整"),
+            DecodeError::InvalidVarint => write!(f, "无效的 varint"),
+            DecodeError::UnsupportedWireType(t) => write!(f, "不支持的 wire type: {}", t),
+            DecodeError::Decompress(e) => write!(f, "解压失败: {}", e),
+            DecodeError::FrameTooLarge => write!(f, "帧大小超过 {} 字节", MAX_FRAME_SIZE),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// 写入 base-128 varint
+pub fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+// 简单的 protobuf 消息编码器，字段按调用顺序写入
+#[derive(Debug, Default)]
+pub struct ProtoWriter {
+    buf: Vec<u8>,
+}
+
+impl ProtoWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(&mut self, field: u32, wire_type: u64) {
+        encode_varint(((field as u64) << 3) | wire_type, &mut self.buf);
+    }
+
+    pub fn uint64(&mut self, field: u32, value: u64) -> &mut Self {
+        self.key(field, WIRE_TYPE_VARINT);
+        encode_varint(value, &mut self.buf);
+        self
+    }
+
+    pub fn bool(&mut self, field: u32, value: bool) -> &mut Self {
+        self.uint64(field, value as u64)
+    }
+
+    pub fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Self {
+        self.key(field, WIRE_TYPE_LEN);
+        encode_varint(value.len() as u64, &mut self.buf);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    // 嵌套消息以 length-delimited 方式写入
+    pub fn message(&mut self, field: u32, message: &ProtoWriter) -> &mut Self {
+        self.bytes(field, &message.buf)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+// 读取 base-128 varint，返回值与消耗的字节数
+pub fn decode_varint(buf: &[u8]) -> Result<(u64, usize), DecodeError> {
+    let mut value = 0u64;
+    for (i, &byte) in buf.iter().enumerate() {
+        if i >= 10 {
+            return Err(DecodeError::InvalidVarint);
+        }
+        value |= ((byte & 0x7F) as u64) << (7 * i);
+        if byte < 0x80 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(DecodeError::Truncated)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FieldValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+// 按顺序遍历 protobuf 消息中的字段
+pub struct ProtoReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> ProtoReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.buf.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (head, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let (value, len) = decode_varint(self.buf)?;
+        self.buf = &self.buf[len..];
+        Ok(value)
+    }
+
+    fn field(&mut self) -> Result<(u32, FieldValue<'a>), DecodeError> {
+        let key = self.varint()?;
+        let field = (key >> 3) as u32;
+        let value = match key & 0x07 {
+            WIRE_TYPE_VARINT => FieldValue::Varint(self.varint()?),
+            WIRE_TYPE_FIXED64 => {
+                FieldValue::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+            }
+            WIRE_TYPE_LEN => {
+                let len = self.varint()? as usize;
+                FieldValue::Bytes(self.take(len)?)
+            }
+            WIRE_TYPE_FIXED32 => {
+                FieldValue::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+            }
+            other => return Err(DecodeError::UnsupportedWireType(other)),
+        };
+        Ok((field, value))
+    }
+}
+
+impl<'a> Iterator for ProtoReader<'a> {
+    type Item = Result<(u32, FieldValue<'a>), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let item = self.field();
+        if item.is_err() {
+            // 出错后停止遍历
+            self.buf = &[];
+        }
+        Some(item)
+    }
+}
+
+// 一个完整的 Connect 帧，负载已解压
+#[derive(Debug, PartialEq, Eq)]
+pub struct Envelope {
+    pub flags: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn is_end_stream(&self) -> bool {
+        self.flags & FLAG_END_STREAM != 0
+    }
+
+    // 从缓冲区头部解析一个帧，返回帧与消耗的字节数；数据不足一帧时返回 None
+    pub fn parse(buf: &[u8]) -> Result<Option<(Envelope, usize)>, DecodeError> {
+        if buf.len() < ENVELOPE_HEADER_LEN {
+            return Ok(None);
+        }
+        let flags = buf[0];
+        let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+        if len > MAX_FRAME_SIZE {
+            return Err(DecodeError::FrameTooLarge);
+        }
+        let total = ENVELOPE_HEADER_LEN + len;
+        if buf.len() < total {
+            return Ok(None);
+        }
+
+        let raw = &buf[ENVELOPE_HEADER_LEN..total];
+        let payload = if flags & FLAG_COMPRESSED != 0 {
+            // 多读一个字节以判断是否超出上限
+            let mut decompressed = Vec::new();
+            GzDecoder::new(raw)
+                .take(MAX_FRAME_SIZE as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| DecodeError::Decompress(e.to_string()))?;
+            if decompressed.len() > MAX_FRAME_SIZE {
+                return Err(DecodeError::FrameTooLarge);
+            }
+            decompressed
+        } else {
+            raw.to_vec()
+        };
+
+        Ok(Some((Envelope { flags, payload }, total)))
+    }
+}
+
+// 缓存上游数据块，直到能取出完整的帧
+#[derive(Debug, Default)]
+pub struct FrameReader {
+    buf: BytesMut,
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    // 取出下一个完整的帧，数据不足时返回 None 并保留已缓存的字节
+    pub fn next_frame(&mut self) -> Result<Option<Envelope>, DecodeError> {
+        match Envelope::parse(&self.buf)? {
+            Some((envelope, used)) => {
+                self.buf.advance(used);
+                Ok(Some(envelope))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // 尚未组成完整帧的字节数
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+// 将 protobuf 负载封装为 Connect 帧
+pub fn encode_envelope(flags: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(ENVELOPE_HEADER_LEN + payload.len());
+    frame.push(flags);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_varint() {
+        let cases: [(u64, &[u8]); 5] = [
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (127, &[0x7F]),
+            (300, &[0xAC, 0x02]),
+            (20_000, &[0xA0, 0x9C, 0x01]),
+        ];
+        for (value, expected) in cases {
+            let mut buf = Vec::new();
+            encode_varint(value, &mut buf);
+            assert_eq!(buf, expected, "value = {}", value);
+        }
+    }
+
+    #[test]
+    fn test_varint_round_trip() {
+        for value in [
+            0,
+            1,
+            127,
+            128,
+            300,
+            16_383,
+            16_384,
+            u32::MAX as u64,
+            u64::MAX,
+        ] {
+            let mut buf = Vec::new();
+            encode_varint(value, &mut buf);
+            assert_eq!(decode_varint(&buf), Ok((value, buf.len())));
+        }
+        assert_eq!(decode_varint(&[0x80, 0x80]), Err(DecodeError::Truncated));
+    }
+
+    #[test]
+    fn test_encode_envelope() {
+        let frame = encode_envelope(0, &[0x0A, 0x01, 0x61]);
+        assert_eq!(frame, [0x00, 0x00, 0x00, 0x00, 0x03, 0x0A, 0x01, 0x61]);
+    }
+
+    #[test]
+    fn test_proto_reader() {
+        let mut w = ProtoWriter::new();
+        w.string(1, "a\n").uint64(2, 300).bool(16, true);
+        let buf = w.into_bytes();
+        let fields = ProtoReader::new(&buf)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            fields,
+            vec![
+                (1, FieldValue::Bytes(b"a\n")),
+                (2, FieldValue::Varint(300)),
+                (16, FieldValue::Varint(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_envelope_parse() {
+        let mut buf = encode_envelope(0, b"abc");
+        buf.extend_from_slice(&encode_envelope(FLAG_END_STREAM, b"{}"));
+
+        let (first, used) = Envelope::parse(&buf).unwrap().unwrap();
+        assert_eq!(first.payload, b"abc");
+        assert!(!first.is_end_stream());
+
+        let (second, rest) = Envelope::parse(&buf[used..]).unwrap().unwrap();
+        assert_eq!(second.payload, b"{}");
+        assert!(second.is_end_stream());
+        assert_eq!(used + rest, buf.len());
+
+        // 帧不完整
+        assert_eq!(Envelope::parse(&buf[..used - 1]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_frame_reader_split_at_every_offset() {
+        let mut stream = encode_envelope(0, b"first frame");
+        stream.extend_from_slice(&encode_envelope(0, b""));
+        stream.extend_from_slice(&encode_envelope(FLAG_END_STREAM, b"{}"));
+
+        for split in 0..=stream.len() {
+            let mut reader = FrameReader::new();
+            let mut frames = Vec::new();
+            for part in [&stream[..split], &stream[split..]] {
+                reader.push(part);
+                while let Some(envelope) = reader.next_frame().unwrap() {
+                    frames.push(envelope);
+                }
+            }
+            assert_eq!(frames.len(), 3, "split = {}", split);
+            assert_eq!(frames[0].payload, b"first frame");
+            assert!(frames[1].payload.is_empty());
+            assert!(frames[2].is_end_stream());
+            assert_eq!(reader.pending(), 0);
+        }
+    }
+
+    #[test]
+    fn test_frame_reader_byte_by_byte() {
+        let stream = encode_envelope(0, b"abc");
+        let mut reader = FrameReader::new();
+        for (i, byte) in stream.iter().enumerate() {
+            reader.push(&[*byte]);
+            let frame = reader.next_frame().unwrap();
+            assert_eq!(frame.is_some(), i == stream.len() - 1);
+        }
+    }
+
+    #[test]
+    fn test_envelope_parse_gzip() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"compressed payload").unwrap();
+        let frame = encode_envelope(FLAG_COMPRESSED, &encoder.finish().unwrap());
+
+        let (envelope, _) = Envelope::parse(&frame).unwrap().unwrap();
+        assert_eq!(envelope.payload, b"compressed payload");
+
+        // 解压后超出上限的帧
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&vec![0; MAX_FRAME_SIZE + 1]).unwrap();
+        let frame = encode_envelope(FLAG_COMPRESSED, &encoder.finish().unwrap());
+        assert_eq!(Envelope::parse(&frame), Err(DecodeError::FrameTooLarge));
+
+        let mut frame = encode_envelope(0, b"");
+        frame[1..5].copy_from_slice(&(MAX_FRAME_SIZE as u32 + 1).to_be_bytes());
+        assert_eq!(Envelope::parse(&frame), Err(DecodeError::FrameTooLarge));
+    }
+}
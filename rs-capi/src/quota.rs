@@ -0,0 +1,248 @@
+use crate::jwt::TokenClaims;
+use crate::state::AppState;
+use crate::token_pool::fingerprint;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const DEFAULT_BASE_URL: &str = "https://www.cursor.com";
+const DEFAULT_CHECK_INTERVAL_SECS: u64 = 600;
+// 用量接口响应很小，不需要共享客户端的长超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 模型所属的额度类别：cursor-small 等小模型计入 gpt-3.5-turbo，其余计入 gpt-4（快速请求）
+pub fn model_family(model: &str) -> &'static str {
+    match model {
+        "cursor-small" | "gpt-3.5-turbo" => "gpt-3.5-turbo",
+        _ => "gpt-4",
+    }
+}
+
+// 某一类模型的请求额度
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModelQuota {
+    pub used: u64,
+    // 为空表示不限
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+}
+
+// 一个 token 对应账号的额度
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenQuota {
+    pub families: BTreeMap<String, ModelQuota>,
+    pub start_of_month: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl TokenQuota {
+    // 解析 /api/usage 的响应，形如 {"gpt-4": {"numRequests": 12, "maxRequestUsage": 500}, "startOfMonth": "..."}
+    pub fn parse(body: &Value) -> Option<Self> {
+        let object = body.as_object()?;
+        let families = object
+            .iter()
+            .filter_map(|(family, usage)| {
+                let used = usage.get("numRequests")?.as_u64()?;
+                let limit = usage.get("maxRequestUsage").and_then(Value::as_u64);
+                Some((
+                    family.clone(),
+                    ModelQuota {
+                        used,
+                        limit,
+                        remaining: limit.map(|limit| limit.saturating_sub(used)),
+                    },
+                ))
+            })
+            .collect();
+        Some(Self {
+            families,
+            start_of_month: object
+                .get("startOfMonth")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            fetched_at: Utc::now(),
+        })
+    }
+
+    // 该模型所属类别的额度是否已用完
+    pub fn is_exhausted(&self, model: &str) -> bool {
+        self.families
+            .get(model_family(model))
+            .is_some_and(|quota| quota.remaining == Some(0))
+    }
+}
+
+// 查询 Cursor 账号用量的客户端
+pub struct QuotaClient {
+    base_url: String,
+    // 后台刷新间隔，为 0 时不启动后台任务
+    pub interval: Duration,
+}
+
+impl QuotaClient {
+    pub fn new(base_url: &str, interval: Duration) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            interval,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let base_url = std::env::var("CURSOR_WEB_BASE_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+        let interval = std::env::var("QUOTA_CHECK_INTERVAL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_CHECK_INTERVAL_SECS);
+        Self::new(&base_url, Duration::from_secs(interval))
+    }
+
+    // 查询 token 所属账号本月的用量，client 应与该 token 请求上游时使用的出口一致
+    pub async fn fetch(&self, client: &reqwest::Client, token: &str) -> Result<TokenQuota, String> {
+        // sub 形如 auth0|user_01...，接口需要的是 | 之后的用户 id
+        let user_id = TokenClaims::decode(token)
+            .and_then(|claims| claims.sub)
+            .and_then(|sub| sub.rsplit('|').next().map(str::to_string))
+            .ok_or_else(|| "无法从 token 中解析用户 id".to_string())?;
+
+        let response = client
+            .get(format!("{}/api/usage", self.base_url))
+            .query(&[("user", &user_id)])
+            .timeout(REQUEST_TIMEOUT)
+            .header(
+                reqwest::header::COOKIE,
+                format!("WorkosCursorSessionToken={}%3A%3A{}", user_id, token),
+            )
+            .send()
+            .await
+            .map_err(|e| format!("请求失败: {}", e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("HTTP {}", status));
+        }
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("响应解析失败: {}", e))?;
+        TokenQuota::parse(&body).ok_or_else(|| "响应格式不正确".to_string())
+    }
+}
+
+// 单个 token 的查询结果
+#[derive(Debug, Serialize)]
+pub struct QuotaReport {
+    pub id: String,
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<TokenQuota>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// 查询所有启用的服务端 token 的额度并更新到 token 池
+pub async fn refresh_all(state: &AppState) -> Vec<QuotaReport> {
+    let mut reports = Vec::new();
+    for token in state.token_pool.server_tokens() {
+        let id = fingerprint(&token);
+        let user = TokenClaims::decode(&token).and_then(|claims| claims.sub);
+        let result = match state.http.get(state.token_pool.proxy(&token).as_deref()) {
+            Ok(client) => state.quota.fetch(&client, &token).await,
+            Err(err) => Err(format!("创建代理客户端失败: {}", err)),
+        };
+        match result {
+            Ok(quota) => {
+                if let Some(fast) = quota.families.get(model_family("gpt-4")) {
+                    tracing::info!(
+                        token = %id,
+                        used = fast.used,
+                        remaining = ?fast.remaining,
+                        "已更新 token 用量"
+                    );
+                }
+                state.token_pool.set_quota(&token, quota.clone());
+                reports.push(QuotaReport {
+                    id,
+                    user,
+                    quota: Some(quota),
+                    error: None,
+                });
+            }
+            Err(err) => {
+                tracing::warn!(token = %id, "查询 token 用量失败: {}", err);
+                reports.push(QuotaReport {
+                    id,
+                    user,
+                    quota: None,
+                    error: Some(err),
+                });
+            }
+        }
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::encode_test_token;
+    use axum::extract::Query;
+    use axum::http::HeaderMap;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_usage() {
+        let quota = TokenQuota::parse(&serde_json::json!({
+            "gpt-4": {"numRequests": 500, "numRequestsTotal": 512, "numTokens": 1000, "maxRequestUsage": 500, "maxTokenUsage": null},
+            "gpt-3.5-turbo": {"numRequests": 30, "maxRequestUsage": null},
+            "startOfMonth": "2024-11-20T08:00:00.000Z"
+        }))
+        .unwrap();
+        assert_eq!(
+            quota.families["gpt-4"],
+            ModelQuota {
+                used: 500,
+                limit: Some(500),
+                remaining: Some(0)
+            }
+        );
+        assert_eq!(quota.families["gpt-3.5-turbo"].remaining, None);
+        assert!(quota.is_exhausted("claude-3.5-sonnet"));
+        assert!(!quota.is_exhausted("cursor-small"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_stub() {
+        async fn usage(
+            Query(query): Query<HashMap<String, String>>,
+            headers: HeaderMap,
+        ) -> Json<Value> {
+            assert_eq!(query["user"], "user_01ABC");
+            let cookie = headers["cookie"].to_str().unwrap();
+            assert!(cookie.starts_with("WorkosCursorSessionToken=user_01ABC%3A%3A"));
+            Json(serde_json::json!({"gpt-4": {"numRequests": 12, "maxRequestUsage": 50}}))
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/api/usage", get(usage)))
+                .await
+                .unwrap();
+        });
+
+        let client = QuotaClient::new(&format!("http://{}/", addr), Duration::ZERO);
+        let http = reqwest::Client::new();
+        let token = encode_test_token(&serde_json::json!({"sub": "auth0|user_01ABC"}));
+        let quota = client.fetch(&http, &token).await.unwrap();
+        assert_eq!(quota.families["gpt-4"].remaining, Some(38));
+
+        assert!(client.fetch(&http, "not-a-jwt").await.is_err());
+    }
+}
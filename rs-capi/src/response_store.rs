@@ -0,0 +1,187 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CAPACITY: usize = 1000;
+const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+const DEFAULT_TTL_SECS: u64 = 24 * 60 * 60;
+
+// 对话中的一条消息，content 为发往上游的提示词文本
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    pub role: String,
+    pub content: String,
+}
+
+impl Turn {
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: content.into(),
+        }
+    }
+}
+
+fn turns_size(turns: &[Turn]) -> usize {
+    turns
+        .iter()
+        .map(|turn| turn.role.len() + turn.content.len())
+        .sum()
+}
+
+struct StoredResponse {
+    // 创建该响应的客户端，只有同一客户端可以续接
+    owner: String,
+    // 截至该响应的完整对话（不含 instructions），续接时无需回溯更早的响应
+    turns: Vec<Turn>,
+    size: usize,
+    created: Instant,
+}
+
+#[derive(Default)]
+struct Inner {
+    responses: HashMap<String, StoredResponse>,
+    // 按写入顺序排列的 id，用于淘汰最早和过期的响应
+    order: VecDeque<String>,
+    // 所有对话的文本字节数
+    bytes: usize,
+}
+
+impl Inner {
+    fn remove_oldest(&mut self) {
+        if let Some(oldest) = self.order.pop_front() {
+            if let Some(response) = self.responses.remove(&oldest) {
+                self.bytes -= response.size;
+            }
+        }
+    }
+}
+
+// 保存在内存中的 Responses 对话，供 previous_response_id 续接，重启后丢失
+pub struct ResponseStore {
+    // 最多保存的响应数，0 表示不保存
+    capacity: usize,
+    // 所有对话合计最多占用的字节数，单个对话超出时不保存
+    max_bytes: usize,
+    ttl: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl ResponseStore {
+    pub fn new(capacity: usize, max_bytes: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            max_bytes,
+            ttl,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("RESPONSE_STORE_CAPACITY")
+            .ok()
+            .and_then(|capacity| capacity.parse().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+        let max_bytes = std::env::var("RESPONSE_STORE_MAX_BYTES")
+            .ok()
+            .and_then(|bytes| bytes.parse().ok())
+            .unwrap_or(DEFAULT_MAX_BYTES);
+        let ttl = std::env::var("RESPONSE_STORE_TTL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECS);
+        Self::new(capacity, max_bytes, Duration::from_secs(ttl))
+    }
+
+    pub fn insert(&self, id: String, owner: String, turns: Vec<Turn>) {
+        let size = turns_size(&turns);
+        if self.capacity == 0 || size > self.max_bytes {
+            if self.capacity > 0 {
+                tracing::warn!("对话过长（{} 字节），不保存响应 {}", size, id);
+            }
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        self.purge_expired(&mut inner);
+        while inner.order.len() >= self.capacity || inner.bytes + size > self.max_bytes {
+            inner.remove_oldest();
+        }
+        inner.order.push_back(id.clone());
+        inner.bytes += size;
+        inner.responses.insert(
+            id,
+            StoredResponse {
+                owner,
+                turns,
+                size,
+                created: Instant::now(),
+            },
+        );
+    }
+
+    // 取出截至该响应的对话，不存在、已过期或不属于该客户端时返回 None
+    pub fn get(&self, id: &str, owner: &str) -> Option<Vec<Turn>> {
+        let mut inner = self.inner.lock().unwrap();
+        self.purge_expired(&mut inner);
+        inner
+            .responses
+            .get(id)
+            .filter(|response| response.owner == owner)
+            .map(|response| response.turns.clone())
+    }
+
+    // 写入顺序即创建时间顺序，从头部开始清理即可
+    fn purge_expired(&self, inner: &mut Inner) {
+        while let Some(oldest) = inner.order.front() {
+            let expired = match inner.responses.get(oldest) {
+                Some(response) => response.created.elapsed() >= self.ttl,
+                None => true,
+            };
+            if !expired {
+                break;
+            }
+            inner.remove_oldest();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capacity_and_ttl() {
+        let store = ResponseStore::new(2, 1024, Duration::from_secs(60));
+        for id in ["a", "b", "c"] {
+            store.insert(id.to_string(), "o".to_string(), vec![Turn::new("user", id)]);
+        }
+        assert_eq!(store.get("a", "o"), None);
+        assert_eq!(store.get("c", "o"), Some(vec![Turn::new("user", "c")]));
+        // 其他客户端看不到该响应
+        assert_eq!(store.get("c", "other"), None);
+
+        let store = ResponseStore::new(2, 1024, Duration::ZERO);
+        store.insert("a".to_string(), "o".to_string(), Vec::new());
+        assert_eq!(store.get("a", "o"), None);
+
+        let store = ResponseStore::new(0, 1024, Duration::from_secs(60));
+        store.insert("a".to_string(), "o".to_string(), Vec::new());
+        assert_eq!(store.get("a", "o"), None);
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let store = ResponseStore::new(10, 20, Duration::from_secs(60));
+        let turn = |content: &str| vec![Turn::new("user", content)];
+        store.insert("a".to_string(), "o".to_string(), turn("12345678"));
+        store.insert("b".to_string(), "o".to_string(), turn("12345678"));
+        // 超出总字节数时淘汰最早的响应
+        assert_eq!(store.get("a", "o"), None);
+        assert!(store.get("b", "o").is_some());
+
+        // 单个对话超出上限时不保存
+        store.insert("c".to_string(), "o".to_string(), turn(&"x".repeat(20)));
+        assert_eq!(store.get("c", "o"), None);
+        assert!(store.get("b", "o").is_some());
+    }
+}
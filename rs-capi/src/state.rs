@@ -0,0 +1,83 @@
+use crate::auth::ApiKeys;
+use crate::http_client::HttpClients;
+use crate::quota::QuotaClient;
+use crate::response_store::ResponseStore;
+use crate::token_pool::TokenPool;
+use crate::upstream::UpstreamConfig;
+use crate::usage::UsageRecorder;
+
+// 各个处理器共享的应用状态
+pub struct AppState {
+    // 共享的出站 HTTP 客户端，按代理区分
+    pub http: HttpClients,
+    pub token_pool: TokenPool,
+    pub api_keys: ApiKeys,
+    pub usage: UsageRecorder,
+    pub quota: QuotaClient,
+    pub upstream: UpstreamConfig,
+    // Responses 接口保存的对话，供 previous_response_id 续接
+    pub responses: ResponseStore,
+    // 管理接口的密钥，未配置时管理接口不可用
+    pub admin_key: Option<String>,
+    // 未启用 API key 时，是否允许不带认证头的请求使用服务端 token
+    pub allow_anonymous: bool,
+}
+
+impl AppState {
+    pub fn from_env(http: HttpClients) -> Self {
+        let state = Self {
+            quota: QuotaClient::from_env(),
+            http,
+            token_pool: TokenPool::from_env(),
+            api_keys: ApiKeys::from_env(),
+            usage: UsageRecorder::from_env(),
+            upstream: UpstreamConfig::from_env(),
+            responses: ResponseStore::from_env(),
+            admin_key: std::env::var("ADMIN_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            allow_anonymous: std::env::var("ALLOW_ANONYMOUS").is_ok_and(|value| {
+                matches!(value.to_ascii_lowercase().as_str(), "true" | "1" | "yes")
+            }),
+        };
+        if state.api_keys.is_enabled() && state.token_pool.server_tokens().is_empty() {
+            tracing::warn!("已启用 API key 认证，但未配置 CURSOR_TOKENS 或 CURSOR_TOKENS_FILE");
+        }
+        if !state.api_keys.is_enabled()
+            && !state.allow_anonymous
+            && !state.token_pool.server_tokens().is_empty()
+        {
+            tracing::warn!(
+                "未配置 API_KEYS，服务端 token 不会被使用；如需匿名使用请设置 ALLOW_ANONYMOUS=true"
+            );
+        }
+        if !state.api_keys.is_enabled() && state.allow_anonymous {
+            tracing::warn!(
+                "已允许匿名请求使用服务端 token，任何能访问该端口的人都可以消耗这些账号的额度"
+            );
+        }
+        state
+    }
+
+    // 测试用的状态：不记录用量、不查询额度，上游指向给定地址
+    #[cfg(test)]
+    pub fn for_test(
+        server_tokens: Vec<String>,
+        api_keys: ApiKeys,
+        upstream_base_url: &str,
+    ) -> Self {
+        use crate::http_client::HttpClientConfig;
+        use std::time::Duration;
+        Self {
+            http: HttpClients::new(HttpClientConfig::default()).unwrap(),
+            token_pool: TokenPool::new(server_tokens, Duration::from_secs(60)),
+            api_keys,
+            usage: UsageRecorder::disabled(),
+            quota: QuotaClient::new("http://127.0.0.1", Duration::ZERO),
+            upstream: UpstreamConfig::new(upstream_base_url, "/aiserver.v1.AiService/StreamChat"),
+            responses: ResponseStore::new(100, 1024 * 1024, Duration::from_secs(60)),
+            admin_key: Some("admin".to_string()),
+            allow_anonymous: false,
+        }
+    }
+}
@@ -0,0 +1,695 @@
+use crate::checksum::{is_valid_id, MachineIdentity};
+use crate::models::error::ApiError;
+use crate::proto::chat::{
+    next_message, ConversationMessage, GetChatRequest, MessageType, StreamMessage,
+};
+use crate::proto::FrameReader;
+use crate::state::AppState;
+use crate::token_pool::{fingerprint, token_user};
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt};
+use serde::Deserialize;
+use uuid::Uuid;
+
+const DEFAULT_BASE_URL: &str = "https://api2.cursor.sh";
+const DEFAULT_CHAT_PATH: &str = "/aiserver.v1.AiService/StreamChat";
+
+const DEFAULT_CLIENT_VERSION: &str = "0.42.3";
+const DEFAULT_TIMEZONE: &str = "Asia/Shanghai";
+const DEFAULT_USER_AGENT: &str = "connect-es/1.4.0";
+
+fn env_value(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+// 上游请求中标识客户端的信息，可全局配置，也可按 token 覆盖；未设置的字段使用默认值
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ClientIdentity {
+    pub client_version: Option<String>,
+    pub timezone: Option<String>,
+    pub user_agent: Option<String>,
+    // 隐私模式，开启后 Cursor 不保留请求内容
+    pub ghost_mode: Option<bool>,
+    // 生成 X-Cursor-Checksum 用的机器标识，未配置时按 token 派生
+    pub machine_id: Option<String>,
+    pub mac_machine_id: Option<String>,
+}
+
+impl ClientIdentity {
+    pub fn from_env() -> Self {
+        let mut identity = Self {
+            client_version: env_value("CURSOR_CLIENT_VERSION"),
+            timezone: env_value("CURSOR_TIMEZONE"),
+            user_agent: env_value("UPSTREAM_USER_AGENT"),
+            ghost_mode: env_value("CURSOR_GHOST_MODE")
+                .map(|value| matches!(value.to_ascii_lowercase().as_str(), "true" | "1" | "yes")),
+            machine_id: env_value("CURSOR_MACHINE_ID"),
+            mac_machine_id: env_value("CURSOR_MAC_MACHINE_ID"),
+        };
+        // 格式不正确的机器标识不使用，改为按 token 派生
+        for (name, id) in [
+            ("CURSOR_MACHINE_ID", &mut identity.machine_id),
+            ("CURSOR_MAC_MACHINE_ID", &mut identity.mac_machine_id),
+        ] {
+            if id.as_deref().is_some_and(|id| !is_valid_id(id)) {
+                tracing::error!("{} 应为 64 位十六进制字符串，已忽略", name);
+                *id = None;
+            }
+        }
+        if identity.ghost_mode == Some(true) {
+            tracing::info!("已启用隐私模式（X-Ghost-Mode）");
+        }
+        identity
+    }
+
+    // 第一个格式不正确的机器标识字段名
+    pub fn invalid_id_field(&self) -> Option<&'static str> {
+        [
+            ("machine_id", &self.machine_id),
+            ("mac_machine_id", &self.mac_machine_id),
+        ]
+        .into_iter()
+        .find(|(_, id)| id.as_deref().is_some_and(|id| !is_valid_id(id)))
+        .map(|(name, _)| name)
+    }
+
+    // 配置的机器标识应为 64 位十六进制，否则上游可能拒绝
+    pub fn validate(&self) -> Result<(), String> {
+        match self.invalid_id_field() {
+            Some(name) => Err(format!("{} 应为 64 位十六进制字符串", name)),
+            None => Ok(()),
+        }
+    }
+
+    // 未设置的字段使用 fallback 中的值
+    pub fn or(self, fallback: &ClientIdentity) -> Self {
+        Self {
+            client_version: self
+                .client_version
+                .or_else(|| fallback.client_version.clone()),
+            timezone: self.timezone.or_else(|| fallback.timezone.clone()),
+            user_agent: self.user_agent.or_else(|| fallback.user_agent.clone()),
+            ghost_mode: self.ghost_mode.or(fallback.ghost_mode),
+            machine_id: self.machine_id.or_else(|| fallback.machine_id.clone()),
+            mac_machine_id: self
+                .mac_machine_id
+                .or_else(|| fallback.mac_machine_id.clone()),
+        }
+    }
+
+    pub fn machine(&self, token: &str) -> MachineIdentity {
+        MachineIdentity::for_token(
+            token,
+            self.machine_id.as_deref(),
+            self.mac_machine_id.as_deref(),
+        )
+    }
+}
+
+// 上游地址，可指向区域节点、出口网关或本地模拟服务
+pub struct UpstreamConfig {
+    pub base_url: String,
+    pub chat_path: String,
+    // 全局的客户端标识
+    pub identity: ClientIdentity,
+}
+
+impl UpstreamConfig {
+    pub fn new(base_url: &str, chat_path: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            chat_path: format!("/{}", chat_path.trim_start_matches('/')),
+            identity: ClientIdentity::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let mut config = Self::new(
+            &env_value("UPSTREAM_BASE_URL").unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            &env_value("UPSTREAM_CHAT_PATH").unwrap_or_else(|| DEFAULT_CHAT_PATH.to_string()),
+        );
+        config.identity = ClientIdentity::from_env();
+        if config.base_url != DEFAULT_BASE_URL {
+            tracing::info!("上游地址: {}", config.base_url);
+        }
+        config
+    }
+
+    pub fn chat_url(&self) -> String {
+        format!("{}{}", self.base_url, self.chat_path)
+    }
+}
+
+// 一次 StreamChat 调用的响应，按消息读取
+pub struct UpstreamChat {
+    stream: BoxStream<'static, reqwest::Result<Bytes>>,
+    reader: FrameReader,
+    peeked: Option<StreamMessage>,
+    token: String,
+}
+
+impl UpstreamChat {
+    // 本次请求实际使用的上游 token
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    // 读取下一条消息，上游响应结束时返回 None
+    pub async fn next(&mut self) -> Result<Option<StreamMessage>, ApiError> {
+        if let Some(message) = self.peeked.take() {
+            return Ok(Some(message));
+        }
+        loop {
+            match next_message(&mut self.reader) {
+                Ok(Some(message)) => return Ok(Some(message)),
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::error!("响应解码失败: {}", err);
+                    return Err(ApiError::Decode(err.to_string()));
+                }
+            }
+            match self.stream.next().await {
+                Some(Ok(chunk)) => self.reader.push(&chunk),
+                Some(Err(err)) => {
+                    tracing::error!("读取上游响应失败: {}", err);
+                    return Err(ApiError::UpstreamRequest(err.to_string()));
+                }
+                None => {
+                    if self.reader.pending() > 0 {
+                        tracing::warn!("响应帧不完整，丢弃 {} 字节", self.reader.pending());
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    // 跳过开头的空文本帧，取得第一条有效消息但不消费它
+    async fn peek(&mut self) -> Result<Option<&StreamMessage>, ApiError> {
+        while self.peeked.is_none() {
+            match self.next().await? {
+                Some(StreamMessage::Text(text)) if text.is_empty() => continue,
+                Some(message) => self.peeked = Some(message),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.peeked.as_ref())
+    }
+}
+
+// 使用指定 token 发起 StreamChat 请求
+async fn send_chat(
+    client: &reqwest::Client,
+    config: &UpstreamConfig,
+    identity: &ClientIdentity,
+    auth_token: &str,
+    prompt: &str,
+    model: &str,
+) -> Result<UpstreamChat, ApiError> {
+    // 生成请求数据
+    let request_id = Uuid::new_v4();
+    let mut chat_body = GetChatRequest::new(
+        vec![ConversationMessage::new(prompt, MessageType::Human)],
+        model,
+    );
+    chat_body.request_id = request_id.to_string();
+
+    // 准备请求头
+    let checksum = identity
+        .machine(auth_token)
+        .checksum(chrono::Utc::now().timestamp_millis());
+    let ghost_mode = if identity.ghost_mode.unwrap_or(false) {
+        "true"
+    } else {
+        "false"
+    };
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (name, value) in [
+        ("Content-Type", "application/connect+proto"),
+        ("Authorization", &format!("Bearer {}", auth_token)),
+        ("Connect-Accept-Encoding", "gzip"),
+        ("Connect-Protocol-Version", "1"),
+        (
+            "User-Agent",
+            identity.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT),
+        ),
+        ("X-Amzn-Trace-Id", &format!("Root={}", Uuid::new_v4())),
+        ("X-Cursor-Checksum", &checksum),
+        (
+            "X-Cursor-Client-Version",
+            identity
+                .client_version
+                .as_deref()
+                .unwrap_or(DEFAULT_CLIENT_VERSION),
+        ),
+        (
+            "X-Cursor-Timezone",
+            identity.timezone.as_deref().unwrap_or(DEFAULT_TIMEZONE),
+        ),
+        ("X-Ghost-Mode", ghost_mode),
+        ("X-Request-Id", &request_id.to_string()),
+    ] {
+        // 值来自配置，可能包含非法字符
+        let value = reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| ApiError::Internal(format!("请求头 {} 的值无效", name)))?;
+        headers.insert(name, value);
+    }
+
+    let response = client
+        .post(config.chat_url())
+        .headers(headers)
+        .body(chat_body.encode_frame())
+        .send()
+        .await
+        .map_err(|e| {
+            tracing::error!("请求失败: {:?}", e);
+            tracing::error!(error = %e, "错误详情");
+
+            // 如果是超时错误
+            if e.is_timeout() {
+                tracing::error!("请求超时");
+            }
+
+            // 如果是连接错误
+            if e.is_connect() {
+                tracing::error!("连接失败");
+            }
+
+            // 如果有请求信息
+            if let Some(url) = e.url() {
+                tracing::error!(url = %url, "请求URL");
+            }
+
+            // 如果有状态码
+            if let Some(status) = e.status() {
+                tracing::error!(status = %status, "HTTP状态码");
+            }
+
+            ApiError::UpstreamRequest(e.to_string())
+        })?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        tracing::error!(status = %status, body = %body, "上游返回错误状态");
+        return Err(ApiError::UpstreamStatus(status.as_u16()));
+    }
+
+    Ok(UpstreamChat {
+        stream: response.bytes_stream().boxed(),
+        reader: FrameReader::new(),
+        peeked: None,
+        token: auth_token.to_string(),
+    })
+}
+
+// 依次尝试候选 token；在向客户端输出任何内容之前，
+// 如果上游返回认证或额度错误，则冷却该 token 并换下一个重试
+pub async fn send_with_failover(
+    state: &AppState,
+    candidates: &[String],
+    prompt: &str,
+    model: &str,
+) -> Result<UpstreamChat, ApiError> {
+    let mut tried: Vec<String> = Vec::new();
+    let mut last_error = None;
+
+    loop {
+        // 跳过已尝试过的和已知额度用完的 token
+        let remaining: Vec<String> = candidates
+            .iter()
+            .filter(|token| !tried.contains(token))
+            .filter(|token| !state.token_pool.is_exhausted(token, model))
+            .cloned()
+            .collect();
+        let Some(token) = state.token_pool.select(&remaining) else {
+            return Err(last_error.unwrap_or(ApiError::NoAvailableToken));
+        };
+        tried.push(token.clone());
+        let user = token_user(&token).unwrap_or_else(|| "-".to_string());
+        tracing::info!(token = %fingerprint(&token), user = %user, "使用 token 请求上游");
+        let identity = state
+            .token_pool
+            .identity(&token)
+            .or(&state.upstream.identity);
+        let client = state
+            .http
+            .get(state.token_pool.proxy(&token).as_deref())
+            .map_err(|e| ApiError::Internal(format!("创建代理客户端失败: {}", e)))?;
+
+        let mut upstream =
+            match send_chat(&client, &state.upstream, &identity, &token, prompt, model).await {
+                Ok(upstream) => upstream,
+                // HTTP 层的认证或限流错误与结束帧中的同类错误一样处理
+                Err(err) if err.is_token_error() => {
+                    let message = err.status_and_body().1.error.message;
+                    tracing::warn!(
+                        attempt = tried.len(),
+                        user = %user,
+                        "token 不可用，切换下一个: {}",
+                        message
+                    );
+                    state.token_pool.cooldown(&token, &message);
+                    last_error = Some(err);
+                    continue;
+                }
+                Err(err) => {
+                    state
+                        .token_pool
+                        .record_error(&token, &err.status_and_body().1.error.message);
+                    return Err(err);
+                }
+            };
+        let peeked = match upstream.peek().await {
+            Ok(peeked) => peeked,
+            Err(err) => {
+                state
+                    .token_pool
+                    .record_error(&token, &err.status_and_body().1.error.message);
+                return Err(err);
+            }
+        };
+        match peeked {
+            Some(StreamMessage::Error(err)) if err.is_token_error() => {
+                tracing::warn!(
+                    attempt = tried.len(),
+                    user = %user,
+                    "token 不可用，切换下一个: {}",
+                    err
+                );
+                state.token_pool.cooldown(&token, &err.to_string());
+                last_error = Some(ApiError::Upstream(err.clone()));
+            }
+            Some(StreamMessage::Error(err)) => {
+                state.token_pool.record_error(&token, &err.to_string());
+                return Ok(upstream);
+            }
+            _ => return Ok(upstream),
+        }
+    }
+}
+
+// 测试用的模拟上游：依次返回给定的文本帧后正常结束
+#[cfg(test)]
+pub async fn spawn_test_upstream(texts: &'static [&'static str]) -> String {
+    use crate::proto::{encode_envelope, ProtoWriter, FLAG_END_STREAM};
+
+    let app = axum::Router::new().route(
+        DEFAULT_CHAT_PATH,
+        axum::routing::post(move || async move {
+            let mut body = Vec::new();
+            for text in texts {
+                let mut writer = ProtoWriter::new();
+                writer.string(1, text);
+                body.extend(encode_envelope(0, &writer.into_bytes()));
+            }
+            body.extend(encode_envelope(FLAG_END_STREAM, b"{}"));
+            body
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+// 测试用的模拟上游：返回一个文本帧后既不结束也不关闭连接；响应被丢弃（连接关闭）时向返回的通道发送通知
+#[cfg(test)]
+pub async fn spawn_stalled_upstream(
+    text: &'static str,
+) -> (String, tokio::sync::mpsc::UnboundedReceiver<()>) {
+    use crate::proto::{encode_envelope, ProtoWriter};
+    use futures::StreamExt;
+
+    struct NotifyOnDrop(tokio::sync::mpsc::UnboundedSender<()>);
+
+    impl Drop for NotifyOnDrop {
+        fn drop(&mut self) {
+            let _ = self.0.send(());
+        }
+    }
+
+    let (closed_tx, closed_rx) = tokio::sync::mpsc::unbounded_channel();
+    let app = axum::Router::new().route(
+        DEFAULT_CHAT_PATH,
+        axum::routing::post(move || async move {
+            let mut writer = ProtoWriter::new();
+            writer.string(1, text);
+            let frame = encode_envelope(0, &writer.into_bytes());
+            let guard = NotifyOnDrop(closed_tx);
+            let body = futures::stream::once(async move { frame })
+                .chain(futures::stream::pending())
+                .map(move |frame| {
+                    let _ = &guard;
+                    Ok::<_, std::convert::Infallible>(frame)
+                });
+            axum::body::Body::from_stream(body)
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}", addr), closed_rx)
+}
+
+// 测试用的状态：上游指向返回给定文本的模拟服务，不配置服务端 token 和 API key
+#[cfg(test)]
+pub async fn test_state(texts: &'static [&'static str]) -> std::sync::Arc<AppState> {
+    use crate::auth::ApiKeys;
+
+    let base_url = spawn_test_upstream(texts).await;
+    std::sync::Arc::new(AppState::for_test(Vec::new(), ApiKeys::new([]), &base_url))
+}
+
+// 测试用：经由完整路由发送 JSON 请求，客户端携带 Cursor token，返回读取完毕的响应
+#[cfg(test)]
+pub async fn post_json(
+    state: &std::sync::Arc<AppState>,
+    uri: &str,
+    body: serde_json::Value,
+) -> axum::http::Response<String> {
+    post_json_as(state, uri, Some("Bearer token"), body).await
+}
+
+// 测试用：同 post_json，authorization 为 None 时不携带认证头
+#[cfg(test)]
+pub async fn post_json_as(
+    state: &std::sync::Arc<AppState>,
+    uri: &str,
+    authorization: Option<&str>,
+    body: serde_json::Value,
+) -> axum::http::Response<String> {
+    let response = send(state, "POST", uri, authorization, Some(body)).await;
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+    axum::http::Response::from_parts(parts, String::from_utf8(body.to_vec()).unwrap())
+}
+
+// 测试用：经由完整路由发送请求，返回未读取的响应体，用于逐段读取流式响应
+#[cfg(test)]
+pub async fn send(
+    state: &std::sync::Arc<AppState>,
+    method: &str,
+    uri: &str,
+    authorization: Option<&str>,
+    body: Option<serde_json::Value>,
+) -> axum::http::Response<axum::body::Body> {
+    use tower::ServiceExt;
+
+    let mut request = axum::http::Request::builder().method(method).uri(uri);
+    if let Some(authorization) = authorization {
+        request = request.header("authorization", authorization);
+    }
+    let body = match body {
+        Some(body) => {
+            request = request.header("content-type", "application/json");
+            axum::body::Body::from(body.to_string())
+        }
+        None => axum::body::Body::empty(),
+    };
+    crate::router(state.clone())
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::ApiKeys;
+    use crate::proto::{encode_envelope, FLAG_END_STREAM};
+    use crate::token_pool::{parse_tokens, TokenSettings};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+
+    // 模拟上游：token 为 expired 时返回认证错误，为 limited 时返回 HTTP 429，否则返回一段文本
+    async fn mock_stream_chat(headers: HeaderMap) -> (StatusCode, Vec<u8>) {
+        let checksum = headers["x-cursor-checksum"].to_str().unwrap();
+        assert_eq!(checksum.len(), 8 + 64 + 1 + 64);
+        // ghost 使用按 token 覆盖的标识，其余使用全局配置
+        let (ghost_mode, version) = if headers["authorization"] == "Bearer ghost" {
+            ("true", "0.45.0")
+        } else {
+            ("false", "0.42.3")
+        };
+        assert_eq!(headers["x-ghost-mode"], ghost_mode);
+        assert_eq!(headers["x-cursor-client-version"], version);
+        assert_eq!(headers["x-cursor-timezone"], "Europe/Berlin");
+        if headers["authorization"] == "Bearer limited" {
+            return (StatusCode::TOO_MANY_REQUESTS, b"rate limited".to_vec());
+        }
+        let mut body = Vec::new();
+        if headers["authorization"] == "Bearer expired" {
+            let error = br#"{"error":{"code":"unauthenticated","message":"Not logged in","details":[{"debug":{"error":"ERROR_NOT_LOGGED_IN"}}]}}"#;
+            body.extend(encode_envelope(FLAG_END_STREAM, error));
+        } else {
+            let mut writer = crate::proto::ProtoWriter::new();
+            writer.string(1, "hello");
+            body.extend(encode_envelope(0, &writer.into_bytes()));
+            body.extend(encode_envelope(FLAG_END_STREAM, b"{}"));
+        }
+        (StatusCode::OK, body)
+    }
+
+    async fn spawn_mock() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/aiserver.v1.AiService/StreamChat", post(mock_stream_chat));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn test_identity_fallback() {
+        let global = ClientIdentity {
+            timezone: Some("UTC".to_string()),
+            ghost_mode: Some(true),
+            ..Default::default()
+        };
+        let identity = ClientIdentity {
+            ghost_mode: Some(false),
+            client_version: Some("0.45.0".to_string()),
+            ..Default::default()
+        }
+        .or(&global);
+        assert_eq!(identity.ghost_mode, Some(false));
+        assert_eq!(identity.timezone.as_deref(), Some("UTC"));
+        assert_eq!(identity.client_version.as_deref(), Some("0.45.0"));
+        assert_eq!(identity.user_agent, None);
+    }
+
+    #[test]
+    fn test_identity_validate() {
+        let valid = "a".repeat(64);
+        let identity = ClientIdentity {
+            machine_id: Some(valid.clone()),
+            mac_machine_id: Some(valid),
+            ..Default::default()
+        };
+        assert!(identity.validate().is_ok());
+
+        let identity = ClientIdentity {
+            mac_machine_id: Some("not-hex".to_string()),
+            ..identity
+        };
+        assert_eq!(identity.invalid_id_field(), Some("mac_machine_id"));
+        assert!(identity.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_url() {
+        let config =
+            UpstreamConfig::new("http://localhost:8080/", "aiserver.v1.AiService/StreamChat");
+        assert_eq!(
+            config.chat_url(),
+            "http://localhost:8080/aiserver.v1.AiService/StreamChat"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failover_against_mock_upstream() {
+        let base_url = spawn_mock().await;
+        let mut state = AppState::for_test(Vec::new(), ApiKeys::new([]), &base_url);
+        state.upstream.identity.timezone = Some("Europe/Berlin".to_string());
+        state.token_pool.add(
+            "ghost".to_string(),
+            TokenSettings {
+                identity: ClientIdentity {
+                    ghost_mode: Some(true),
+                    client_version: Some("0.45.0".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let upstream =
+            send_with_failover(&state, &parse_tokens("ghost"), "user:hi", "gpt-4o").await;
+        assert_eq!(upstream.unwrap().token(), "ghost");
+
+        let candidates = parse_tokens("expired,valid");
+
+        let mut upstream = send_with_failover(&state, &candidates, "user:hi", "gpt-4o")
+            .await
+            .unwrap();
+        assert_eq!(upstream.token(), "valid");
+        assert!(
+            matches!(upstream.next().await, Ok(Some(StreamMessage::Text(text))) if text == "hello")
+        );
+        assert!(matches!(
+            upstream.next().await,
+            Ok(Some(StreamMessage::End))
+        ));
+
+        // 过期的 token 进入冷却，只剩它时直接返回
+        let result =
+            send_with_failover(&state, &parse_tokens("expired"), "user:hi", "gpt-4o").await;
+        assert!(matches!(result, Err(ApiError::NoAvailableToken)));
+    }
+
+    #[tokio::test]
+    async fn test_failover_on_http_429() {
+        let base_url = spawn_mock().await;
+        let mut state = AppState::for_test(Vec::new(), ApiKeys::new([]), &base_url);
+        state.upstream.identity.timezone = Some("Europe/Berlin".to_string());
+
+        let upstream =
+            send_with_failover(&state, &parse_tokens("limited,valid"), "user:hi", "gpt-4o")
+                .await
+                .unwrap();
+        assert_eq!(upstream.token(), "valid");
+        // 被限流的 token 进入冷却
+        assert_eq!(state.token_pool.select(&parse_tokens("limited")), None);
+
+        // 没有其他 token 时返回上游的状态码
+        let mut state = AppState::for_test(Vec::new(), ApiKeys::new([]), &base_url);
+        state.upstream.identity.timezone = Some("Europe/Berlin".to_string());
+        let result =
+            send_with_failover(&state, &parse_tokens("limited"), "user:hi", "gpt-4o").await;
+        assert!(matches!(result, Err(ApiError::UpstreamStatus(429))));
+    }
+
+    #[tokio::test]
+    async fn test_per_token_proxy() {
+        // 上游地址无法解析，只有经由代理（模拟服务按绝对路径处理请求）才能成功
+        let proxy = spawn_mock().await;
+        let mut state = AppState::for_test(Vec::new(), ApiKeys::new([]), "http://cursor.invalid");
+        state.upstream.identity.timezone = Some("Europe/Berlin".to_string());
+        state.token_pool.add(
+            "proxied".to_string(),
+            TokenSettings {
+                proxy: Some(proxy),
+                ..Default::default()
+            },
+        );
+        let upstream =
+            send_with_failover(&state, &parse_tokens("proxied"), "user:hi", "gpt-4o").await;
+        assert_eq!(upstream.unwrap().token(), "proxied");
+
+        state
+            .token_pool
+            .add("direct".to_string(), TokenSettings::default());
+        let result = send_with_failover(&state, &parse_tokens("direct"), "user:hi", "gpt-4o").await;
+        assert!(matches!(result, Err(ApiError::UpstreamRequest(_))));
+    }
+}
@@ -0,0 +1,391 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+// 过期记录的清理间隔
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_DB_PATH: &str = "usage.db";
+const DEFAULT_RETENTION_DAYS: i64 = 90;
+
+// 一次补全请求的用量记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRecord {
+    pub timestamp: DateTime<Utc>,
+    // 代理 API key 的指纹（SHA-256 前 12 位），用于统计，未启用 API key 认证时为空
+    pub api_key_fingerprint: Option<String>,
+    // 代理 API key 的名称，仅用于展示，未命名的 key 为前几位字符
+    pub api_key: Option<String>,
+    // 实际使用的上游 token 指纹
+    pub token_fingerprint: Option<String>,
+    pub model: String,
+    pub stream: bool,
+    pub prompt_chars: u64,
+    pub completion_chars: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub latency_ms: u64,
+    // 返回给客户端的 HTTP 状态码，流式中途出错时为错误对应的状态码，客户端断开为 499
+    pub status: u16,
+}
+
+// 粗略估算 token 数：ASCII 字符约 4 个一个 token，其余字符（如中文）每个算一个
+pub fn estimate_tokens(text: &str) -> u64 {
+    let ascii = text.bytes().filter(u8::is_ascii).count() as u64;
+    let other = text.chars().filter(|c| !c.is_ascii()).count() as u64;
+    ascii.div_ceil(4) + other
+}
+
+// 估算的提示与回复 token 数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenCounts {
+    pub prompt: u64,
+    pub completion: u64,
+}
+
+// SQLite 用量库
+pub struct UsageDb {
+    conn: Connection,
+}
+
+impl UsageDb {
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    fn init(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS usage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at INTEGER NOT NULL,
+                api_key TEXT,
+                token_fingerprint TEXT,
+                model TEXT NOT NULL,
+                stream INTEGER NOT NULL,
+                prompt_chars INTEGER NOT NULL,
+                completion_chars INTEGER NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                latency_ms INTEGER NOT NULL,
+                status INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_usage_created_at ON usage (created_at);",
+        )?;
+        // 旧版本的表没有 api_key_fingerprint 列
+        let has_fingerprint = conn
+            .prepare("SELECT 1 FROM pragma_table_info('usage') WHERE name = 'api_key_fingerprint'")?
+            .exists([])?;
+        if !has_fingerprint {
+            conn.execute_batch("ALTER TABLE usage ADD COLUMN api_key_fingerprint TEXT;")?;
+        }
+        conn.execute_batch(
+            "DROP INDEX IF EXISTS idx_usage_api_key;
+            CREATE INDEX IF NOT EXISTS idx_usage_api_key_fingerprint
+                ON usage (api_key_fingerprint, created_at);",
+        )?;
+        Ok(Self { conn })
+    }
+
+    pub fn insert(&self, record: &UsageRecord) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO usage (created_at, api_key_fingerprint, api_key, token_fingerprint, model,
+                stream, prompt_chars, completion_chars, prompt_tokens, completion_tokens,
+                latency_ms, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                record.timestamp.timestamp_millis(),
+                record.api_key_fingerprint,
+                record.api_key,
+                record.token_fingerprint,
+                record.model,
+                record.stream,
+                record.prompt_chars,
+                record.completion_chars,
+                record.prompt_tokens,
+                record.completion_tokens,
+                record.latency_ms,
+                record.status,
+            ],
+        )?;
+        Ok(())
+    }
+
+    // 删除早于指定时间的记录，返回删除的行数
+    pub fn purge_before(&self, before: DateTime<Utc>) -> rusqlite::Result<usize> {
+        self.conn.execute(
+            "DELETE FROM usage WHERE created_at < ?1",
+            params![before.timestamp_millis()],
+        )
+    }
+}
+
+// 异步记录用量：处理器只负责发送，由独立线程写入 SQLite，避免阻塞请求
+#[derive(Clone)]
+pub struct UsageRecorder {
+    // 未启用用量记录时为 None
+    tx: Option<mpsc::Sender<UsageRecord>>,
+}
+
+impl UsageRecorder {
+    pub fn disabled() -> Self {
+        Self { tx: None }
+    }
+
+    // retention_days 为 0 时不清理
+    pub fn spawn(db: UsageDb, retention_days: i64) -> Self {
+        let (tx, rx) = mpsc::channel::<UsageRecord>();
+        std::thread::spawn(move || {
+            let purge = |db: &UsageDb| {
+                if retention_days <= 0 {
+                    return;
+                }
+                let before = Utc::now() - chrono::Duration::days(retention_days);
+                match db.purge_before(before) {
+                    Ok(0) => {}
+                    Ok(removed) => tracing::info!("已清理 {} 条过期用量记录", removed),
+                    Err(err) => tracing::error!("清理用量记录失败: {}", err),
+                }
+            };
+
+            purge(&db);
+            let mut last_purge = Instant::now();
+            loop {
+                match rx.recv_timeout(PURGE_INTERVAL) {
+                    Ok(record) => {
+                        if let Err(err) = db.insert(&record) {
+                            tracing::error!("写入用量记录失败: {}", err);
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                if last_purge.elapsed() >= PURGE_INTERVAL {
+                    purge(&db);
+                    last_purge = Instant::now();
+                }
+            }
+        });
+        Self { tx: Some(tx) }
+    }
+
+    // 测试用：记录发送到返回的通道而不写入数据库
+    #[cfg(test)]
+    pub fn capture() -> (Self, mpsc::Receiver<UsageRecord>) {
+        let (tx, rx) = mpsc::channel();
+        (Self { tx: Some(tx) }, rx)
+    }
+
+    pub fn from_env() -> Self {
+        let path = std::env::var("USAGE_DB_PATH").unwrap_or_else(|_| DEFAULT_DB_PATH.to_string());
+        if path.is_empty() {
+            tracing::info!("未启用用量记录");
+            return Self::disabled();
+        }
+        let retention_days = std::env::var("USAGE_RETENTION_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+
+        match UsageDb::open(&path) {
+            Ok(db) => {
+                tracing::info!("用量记录写入 {}，保留 {} 天", path, retention_days);
+                Self::spawn(db, retention_days)
+            }
+            Err(err) => {
+                tracing::error!("打开用量数据库 {} 失败，不记录用量: {}", path, err);
+                Self::disabled()
+            }
+        }
+    }
+
+    pub fn record(&self, record: UsageRecord) {
+        if let Some(tx) = &self.tx {
+            if tx.send(record).is_err() {
+                tracing::error!("用量记录线程已退出");
+            }
+        }
+    }
+
+    // 在请求上游之前开始计量，api_key 为 key 的指纹和名称
+    pub fn start(
+        &self,
+        api_key: Option<(String, String)>,
+        model: &str,
+        stream: bool,
+        prompt: &str,
+    ) -> PendingUsage {
+        PendingUsage {
+            recorder: self.clone(),
+            started: Instant::now(),
+            record: UsageRecord {
+                timestamp: Utc::now(),
+                api_key_fingerprint: api_key.as_ref().map(|(fingerprint, _)| fingerprint.clone()),
+                api_key: api_key.map(|(_, name)| name),
+                token_fingerprint: None,
+                model: model.to_string(),
+                stream,
+                prompt_chars: prompt.chars().count() as u64,
+                completion_chars: 0,
+                prompt_tokens: estimate_tokens(prompt),
+                completion_tokens: 0,
+                latency_ms: 0,
+                status: 0,
+            },
+            finished: false,
+        }
+    }
+}
+
+// 客户端断开时记录的状态码（沿用 nginx 的 499）
+pub const CLIENT_CLOSED_REQUEST: u16 = 499;
+
+// 进行中的请求的用量，结束时调用 finish 写入；
+// 未调用 finish 就被丢弃（如非流式请求的客户端断开，处理器被取消）时按 499 写入
+pub struct PendingUsage {
+    recorder: UsageRecorder,
+    started: Instant,
+    record: UsageRecord,
+    finished: bool,
+}
+
+impl PendingUsage {
+    pub fn set_token_fingerprint(&mut self, fingerprint: String) {
+        self.record.token_fingerprint = Some(fingerprint);
+    }
+
+    pub fn tokens(&self) -> TokenCounts {
+        TokenCounts {
+            prompt: self.record.prompt_tokens,
+            completion: self.record.completion_tokens,
+        }
+    }
+
+    pub fn add_completion(&mut self, text: &str) {
+        self.record.completion_chars += text.chars().count() as u64;
+        self.record.completion_tokens += estimate_tokens(text);
+    }
+
+    pub fn finish(mut self, status: u16) {
+        self.write(status);
+    }
+
+    fn write(&mut self, status: u16) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        self.record.latency_ms = self.started.elapsed().as_millis() as u64;
+        self.record.status = status;
+        self.recorder.record(self.record.clone());
+    }
+}
+
+impl Drop for PendingUsage {
+    fn drop(&mut self) {
+        self.write(CLIENT_CLOSED_REQUEST);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("hello"), 2);
+        assert_eq!(estimate_tokens("你好, world"), 4);
+    }
+
+    #[test]
+    fn test_pending_usage_written_once() {
+        let (recorder, records) = UsageRecorder::capture();
+        recorder.start(None, "gpt-4o", false, "hi").finish(200);
+        assert_eq!(records.try_recv().unwrap().status, 200);
+        assert!(records.try_recv().is_err());
+
+        // 未结束就被丢弃时按客户端断开记录
+        drop(recorder.start(None, "gpt-4o", false, "hi"));
+        assert_eq!(records.try_recv().unwrap().status, CLIENT_CLOSED_REQUEST);
+        assert!(records.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_insert_and_purge() {
+        let db = UsageDb::init(Connection::open_in_memory().unwrap()).unwrap();
+        let recorder = UsageRecorder::disabled();
+        let mut pending = recorder.start(
+            Some(("0123456789ab".to_string(), "team-a".to_string())),
+            "gpt-4o",
+            true,
+            "你好",
+        );
+        pending.add_completion("hello");
+        pending.record.status = 200;
+
+        let old = UsageRecord {
+            timestamp: Utc::now() - chrono::Duration::days(10),
+            ..pending.record.clone()
+        };
+        db.insert(&pending.record).unwrap();
+        db.insert(&old).unwrap();
+
+        let (count, completion_tokens): (i64, i64) = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*), SUM(completion_tokens) FROM usage
+                 WHERE api_key_fingerprint = '0123456789ab' AND api_key = 'team-a'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((count, completion_tokens), (2, 4));
+
+        let removed = db
+            .purge_before(Utc::now() - chrono::Duration::days(1))
+            .unwrap();
+        assert_eq!(removed, 1);
+    }
+
+    // 旧版本的表补上 api_key_fingerprint 列
+    #[test]
+    fn test_migrate_api_key_fingerprint() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE usage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at INTEGER NOT NULL,
+                api_key TEXT,
+                token_fingerprint TEXT,
+                model TEXT NOT NULL,
+                stream INTEGER NOT NULL,
+                prompt_chars INTEGER NOT NULL,
+                completion_chars INTEGER NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                latency_ms INTEGER NOT NULL,
+                status INTEGER NOT NULL
+            );
+            CREATE INDEX idx_usage_api_key ON usage (api_key, created_at);",
+        )
+        .unwrap();
+        let db = UsageDb::init(conn).unwrap();
+        let recorder = UsageRecorder::disabled();
+        let pending = recorder.start(
+            Some(("0123456789ab".to_string(), "team-a".to_string())),
+            "gpt-4o",
+            false,
+            "hi",
+        );
+        db.insert(&pending.record).unwrap();
+        // 再次打开时不重复迁移
+        let db = UsageDb::init(db.conn).unwrap();
+        let fingerprint: String = db
+            .conn
+            .query_row("SELECT api_key_fingerprint FROM usage", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(fingerprint, "0123456789ab");
+    }
+}